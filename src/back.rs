@@ -3,97 +3,99 @@ use erase_output::Erase;
 use file_lock::{FileLock, FileOptions};
 use futures::{
     future::{join_all, Either},
-    stream, FutureExt, StreamExt,
+    pin_mut, Stream, StreamExt,
 };
 use itertools::Itertools;
 use mmagolf::{
-    codetest, connect_to_server, display_compile_error, submit, Command, ReternMessage, Submission,
-    SubmissionResultType,
+    client::{Client, CodetestResult, CompileError, JudgeEvent, JudgeResult},
+    color::{self, Palette},
+    config::{Config, ShowPolicy},
+    format_diff, format_table,
+    messages::{self, Locale, Msg},
+    printable, rank_submissions, Command, Error, ExitCode, JudgeStatus, Lang, LanguageShortests,
+    OutputFormat, Problem, Progress, SortKey, Submission, TestCase,
 };
 use serde_json::json;
 use slack_hook::{PayloadBuilder, Slack};
 use std::{
-    collections::HashMap,
-    fmt::Display,
+    collections::{HashMap, HashSet},
+    future::{self, Future},
     io::{Read, Write},
     iter,
     net::TcpStream,
     path::{Path, PathBuf},
     process::exit,
+    time::Duration,
 };
-use termion::{color, style};
 use tokio::{
     fs::{self, OpenOptions},
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::mpsc::{channel, Receiver},
-    task,
+    io::AsyncWriteExt,
+    signal, task,
+    time::interval,
 };
 use users::{get_current_uid, get_user_by_uid};
 
-#[cfg(not(feature = "localhost_server"))]
-const SERVER_ADDRESS: &str = "atlas";
-#[cfg(feature = "localhost_server")]
-const SERVER_ADDRESS: &str = "localhost";
-
 #[tokio::main]
 async fn main() {
-    let input = read_input();
-    let ws_stream = connect_to_server(SERVER_ADDRESS).map(|s| {
-        s.unwrap_or_else(|e| {
-            use tokio_tungstenite::tungstenite::Error::*;
-            match e {
-                Io(e) => {
-                    eprintln!(
-                        "ジャッジサーバーに接続できませんでした。\
-                ジャッジサーバーが動いていないかもしれません。{}",
-                        e
-                    );
-                    exit(1);
-                }
-                _ => {
-                    eprintln!(
-                        "ジャッジサーバーに接続できませんでした。原因はよくわかりません。:{}",
-                        e
-                    );
-                    exit(1);
-                }
-            }
-        })
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1);
     });
-    let (input, ws_stream) = tokio::join!(input, ws_stream);
-    match input {
+    messages::set_locale(config.locale);
+    if let Err(e) = async { run(read_input()?, config).await }.await {
+        eprintln!("{e}");
+        e.exit_code().exit();
+    }
+}
+
+async fn run(command: Command, config: Config) -> Result<(), Error> {
+    let palette = config.palette();
+    match command {
         Command::Submit {
             code,
             lang,
             problem_name,
             dry_run,
+            format,
+            progress,
         } => {
-            let (sender, receiver) = channel(100);
-            let submission = submit(&lang, &problem_name, &code, ws_stream, sender);
-            let display_result = display_result(receiver, code.len());
-            let (_, result) = futures::join!(submission, display_result);
-            if matches!(result, Some(JudgeStatus::Ac(_))) {
+            let code = base64::decode(code)?;
+            let mut client = connect(&config).await?;
+            let mut drawn = String::new();
+            let events = client.submit(&lang, &problem_name, &code, ctrl_c());
+            let result = with_deadline(
+                &config,
+                display_result(
+                    events,
+                    code.len(),
+                    format,
+                    progress.resolve(),
+                    &palette,
+                    &mut drawn,
+                ),
+            )
+            .await;
+            let result = give_up_on_timeout(result, &mut client, &drawn).await?;
+            explain_stop(&result);
+            let mut placement = None;
+            if let JudgeResult::Judged(JudgeStatus::Ac(_), _) = result {
                 let (problems, new_submission_id, mut file, language_shortests) =
-                    get_submission_list().await;
+                    get_submission_list(&config.data_dir).await?;
                 let new_submission = &Submission {
                     id: new_submission_id,
                     size: code.len(),
                     problem: problem_name,
                     lang,
                     time: Utc::now(),
-                    user: get_user_by_uid(get_current_uid())
-                        .unwrap()
-                        .name()
-                        .to_string_lossy()
-                        .to_string(),
+                    user: current_user_name()?,
                 };
                 let s_str = format!("{}\n", new_submission);
-                let write1 = task::spawn_blocking(move || {
-                    if !dry_run {
-                        file.file.write_all(s_str.as_bytes()).unwrap()
-                    }
-                });
-                let write2 = save_submission(&code, new_submission_id);
+                let path = config.data_dir.join("submissions");
+                let write1 = async move {
+                    task::block_in_place(|| file.file.write_all(s_str.as_bytes()))
+                        .map_err(|e| Error::Io(path, e))
+                };
+                let write2 = save_submission(&config.data_dir, &code, new_submission_id);
                 let (position, submissions) = insert_submission(problems, new_submission.clone());
                 let mut is_language_shortest = false;
                 let write3 = if language_shortests
@@ -105,106 +107,577 @@ async fn main() {
                     .unwrap_or(true)
                 {
                     is_language_shortest = true;
-                    let submitted_files = SubmittedFiles::new(new_submission_id, code.clone());
+                    let submitted_files =
+                        SubmittedFiles::new(&config.data_dir, new_submission_id, code.clone());
                     Either::Left(make_ranking(&submissions, position, submitted_files))
                 } else {
-                    Either::Right(async {})
+                    Either::Right(async { Ok(()) })
                 };
                 if !dry_run {
-                    let (a, _, _) = futures::join!(write1, write2, write3);
-                    a.unwrap();
+                    let (a, b, c) = futures::join!(write1, write2, write3);
+                    a?;
+                    b?;
+                    // The submission is recorded by now. Publishing it only warns so that
+                    // an accepted submission still reports its result.
+                    if let Err(e) = c {
+                        eprintln!("{e}");
+                    }
                 }
-                match submissions[&new_submission.problem]
-                    .get(0)
-                    .map(|shortest| new_submission.id == shortest.id)
-                {
-                    None | Some(true) => shortest(new_submission, &code, dry_run),
-                    _ if is_language_shortest => {
-                        println!("Shortest code in {}! 🎉", new_submission.lang)
+                let is_shortest = submissions[&new_submission.problem][0].id == new_submission.id;
+                if is_shortest {
+                    if let Err(e) =
+                        shortest(new_submission, &code, dry_run, config.notification_locale)
+                    {
+                        eprintln!("{e}");
+                    }
+                }
+                placement = Some(Placement {
+                    rank: position + 1,
+                    shortest: is_shortest,
+                    language_shortest: is_language_shortest,
+                });
+                if format == OutputFormat::Text {
+                    if is_shortest {
+                        println!("{}", Msg::Shortest);
+                    } else if is_language_shortest {
+                        println!("{}", Msg::LanguageShortest(&new_submission.lang));
                     }
-                    _ => (),
                 }
             }
+            print_summary(format, &summary(&result, code.len(), placement));
+            result.exit_code().exit();
         }
-        Command::Codetest { code, lang, input } => {
-            codetest(
-                lang,
-                code,
-                input.map(|i| base64::decode(i).unwrap()),
-                ws_stream,
+        Command::Codetest {
+            code,
+            lang,
+            problem_name: Some(problem_name),
+            format,
+            progress,
+            ..
+        } => {
+            let code = base64::decode(code)?;
+            let mut client = connect(&config).await?;
+            let mut drawn = String::new();
+            let events = client.run_samples(&lang, &problem_name, &code, ctrl_c());
+            let result = with_deadline(
+                &config,
+                display_result(
+                    events,
+                    code.len(),
+                    format,
+                    progress.resolve(),
+                    &palette,
+                    &mut drawn,
+                ),
             )
             .await;
+            let result = give_up_on_timeout(result, &mut client, &drawn).await?;
+            explain_stop(&result);
+            print_summary(format, &summary(&result, code.len(), None));
+            result.exit_code().exit();
+        }
+        Command::Codetest {
+            code,
+            lang,
+            input,
+            cases,
+            problem_name: None,
+            format,
+            ..
+        } => {
+            let mut client = connect(&config).await?;
+            let code = base64::decode(code)?;
+            if cases.is_empty() {
+                let input = input.map(base64::decode).transpose()?;
+                let codetest = codetest(&mut client, &lang, &code, input.as_deref(), format);
+                let result = with_deadline(&config, codetest).await;
+                give_up_on_timeout(result, &mut client, "").await?.exit();
+            } else {
+                let codetest = codetest_cases(&mut client, &lang, &code, &cases, format, &palette);
+                let result = with_deadline(&config, codetest).await;
+                give_up_on_timeout(result, &mut client, "").await?.exit();
+            }
+        }
+        Command::ListProblems { problem_name, json } => {
+            let mut client = connect(&config).await?;
+            let problems = with_deadline(&config, client.list_problems()).await?;
+            if json {
+                let problems: Vec<_> = problems
+                    .into_iter()
+                    .filter(|p| problem_name.iter().all(|n| &p.name == n))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&problems).unwrap())
+            } else {
+                display_problems(problems, problem_name.as_deref(), &palette)
+            }
+        }
+        Command::ListLangs { json } => {
+            let mut client = connect(&config).await?;
+            let langs = with_deadline(&config, client.list_langs()).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&langs).unwrap())
+            } else {
+                display_langs(&langs)
+            }
+        }
+        Command::Ranking {
+            problem_name,
+            top,
+            by_lang,
+            overall,
+        } => {
+            let submissions = parse_submissions(&read_submission_log(&config.data_dir).await?)?;
+            if overall {
+                display_leaderboard(submissions, &palette);
+            } else {
+                display_ranking(submissions, problem_name.as_deref(), top, by_lang, &palette);
+            }
+        }
+        Command::History {
+            user,
+            all_users,
+            problem_name,
+            lang,
+            since,
+            until,
+            sort,
+            json,
+        } => {
+            let user = if all_users {
+                None
+            } else {
+                Some(user.map_or_else(current_user_name, Ok)?)
+            };
+            let submissions = parse_submissions(&read_submission_log(&config.data_dir).await?)?;
+            let (problems, _) = rank_submissions(submissions.clone());
+            let shortest: HashSet<usize> = problems.values().map(|r| r[0].id).collect();
+            let language_shortest: HashSet<usize> = problems
+                .values()
+                .flat_map(|r| r.iter().unique_by(|s| &s.lang).map(|s| s.id))
+                .collect();
+            let mut submissions: Vec<_> = submissions
+                .into_iter()
+                .filter(|s| {
+                    let date = DateTime::<Local>::from(s.time).naive_local().date();
+                    user.iter().all(|u| &s.user == u)
+                        && problem_name.iter().all(|p| &s.problem == p)
+                        && lang.iter().all(|l| &s.lang == l)
+                        && since.iter().all(|&d| d <= date)
+                        && until.iter().all(|&d| date <= d)
+                })
+                .collect();
+            if let SortKey::Size = sort {
+                submissions.sort_by_key(|s| (s.size, s.id));
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&submissions).unwrap());
+                return Ok(());
+            }
+            let rows: Vec<_> = iter::once(
+                ["id", "size", "problem", "lang", "user", "time", ""]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .chain(submissions.iter().map(|s| {
+                let time: DateTime<Local> = DateTime::from(s.time);
+                let mark = if shortest.contains(&s.id) {
+                    Msg::HistoryShortest.to_string()
+                } else if language_shortest.contains(&s.id) {
+                    Msg::HistoryLanguageShortest(&s.lang).to_string()
+                } else {
+                    String::new()
+                };
+                vec![
+                    s.id.to_string(),
+                    format!("{} B", s.size),
+                    s.problem.clone(),
+                    s.lang.clone(),
+                    s.user.clone(),
+                    time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    mark,
+                ]
+            }))
+            .collect();
+            print_table(&rows, &[0, 1]);
+        }
+        Command::Show { id, hex, raw } => {
+            let submissions = parse_submissions(&read_submission_log(&config.data_dir).await?)?;
+            let submission = submissions.get(id).ok_or(Error::NotSuchSubmission(id))?;
+            let readable = match config.show_policy {
+                ShowPolicy::All => true,
+                ShowPolicy::AfterContest if !config.contest_is_running() => true,
+                _ => submission.user == current_user_name()?,
+            };
+            if !readable {
+                return Err(Error::PermissionDenied);
+            }
+            let code = read_code(&config.data_dir.join("submitted_files"), id).await?;
+            let mut stdout = tokio::io::stdout();
+            if !raw {
+                let time: DateTime<Local> = DateTime::from(submission.time);
+                let meta = format!(
+                    "id: {}\nproblem: {}\nlang: {}\nuser: {}\nsize: {} B\ntime: {}\n\n",
+                    submission.id,
+                    submission.problem,
+                    submission.lang,
+                    submission.user,
+                    submission.size,
+                    time.format("%Y-%m-%d %H:%M:%S"),
+                );
+                stdout
+                    .write_all(meta.as_bytes())
+                    .await
+                    .map_err(Error::Output)?;
+            }
+            if hex {
+                stdout
+                    .write_all(hex_dump(&code).as_bytes())
+                    .await
+                    .map_err(Error::Output)?;
+            } else if raw {
+                stdout.write_all(&code).await.map_err(Error::Output)?;
+            } else {
+                stdout
+                    .write_all(printable(&code).as_bytes())
+                    .await
+                    .map_err(Error::Output)?;
+            }
+            stdout.flush().await.map_err(Error::Output)?;
         }
     }
+    Ok(())
 }
 
-async fn read_input() -> Command {
-    let mut input = String::new();
-    tokio::io::stdin().read_to_string(&mut input).await.unwrap();
-    serde_json::from_str(&input).unwrap()
+/// Runs `code` once and prints what it wrote.
+async fn codetest(
+    client: &mut Client,
+    lang: &str,
+    code: &[u8],
+    input: Option<&[u8]>,
+    format: OutputFormat,
+) -> Result<ExitCode, Error> {
+    let output = match client.codetest(lang, code, input).await? {
+        CodetestResult::Ran(output) => output,
+        result => return codetest_failure(result, format),
+    };
+    if format != OutputFormat::Text {
+        print_summary(
+            format,
+            &with_output(
+                json!({
+                    "type": "codetest_result",
+                    "time": output.time,
+                    "killed": output.killed,
+                    "exit_status": output.exit_status,
+                }),
+                &output.stdout,
+                &output.stderr,
+            ),
+        );
+        return Ok(codetest_exit_code(output.killed));
+    }
+    if output.killed {
+        println!("{}", Msg::CodetestTle);
+    }
+    println!("{}", Msg::CodetestTime(output.time));
+    println!("{}", Msg::CodetestExitStatus(&output.exit_status));
+    write_output(&output.stdout, &output.stderr)?;
+    Ok(codetest_exit_code(output.killed))
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-#[allow(unused)]
-enum JudgeStatus {
-    Ac(u64),
-    // Mle(u64),
-    Tle(u64),
-    Re(u64),
-    // Ole,
-    // Ie,
-    Wa(u64),
-    Wj,
+/// Shows a codetest that did not run.
+fn codetest_failure(result: CodetestResult, format: OutputFormat) -> Result<ExitCode, Error> {
+    Ok(match result {
+        CodetestResult::Ran(_) => unreachable!(),
+        CodetestResult::NotSuchLang(lang) => {
+            if format == OutputFormat::Text {
+                println!("{}", Msg::NotSuchLang(&lang));
+            } else {
+                print_summary(format, &json!({ "type": "not_such_lang", "lang": lang }));
+            }
+            ExitCode::NotSuchLang
+        }
+        CodetestResult::CompileError(e) => {
+            if format == OutputFormat::Text {
+                display_compile_error(&e)?;
+            } else {
+                print_summary(format, &compile_error_event(&e));
+            }
+            ExitCode::CompileError
+        }
+    })
 }
 
-impl Display for JudgeStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            JudgeStatus::Ac(t) => write!(
-                f,
-                "{}{}AC{}{}  {t: >7} ms",
-                style::Bold,
-                color::Fg(color::Green),
-                color::Fg(color::Reset),
-                style::Reset,
-            ),
-            JudgeStatus::Tle(t) => write!(
-                f,
-                "{}{}TLE{}{}  {t: >7} ms",
-                style::Bold,
-                color::Fg(color::Yellow),
-                color::Fg(color::Reset),
-                style::Reset,
-            ),
-            JudgeStatus::Wa(t) => write!(
-                f,
-                "{}{}WA{}{}  {t: >7} ms",
-                style::Bold,
-                color::Fg(color::Yellow),
-                color::Fg(color::Reset),
-                style::Reset,
+/// A codetest only fails by the time limit. The exit status of the program is reported as is.
+fn codetest_exit_code(killed: bool) -> ExitCode {
+    if killed {
+        ExitCode::Tle
+    } else {
+        ExitCode::Success
+    }
+}
+
+/// Runs `code` on every case over one connection and compares stdout with the
+/// expected output of each case.
+async fn codetest_cases(
+    client: &mut Client,
+    lang: &str,
+    code: &[u8],
+    cases: &[TestCase],
+    format: OutputFormat,
+    palette: &Palette,
+) -> Result<ExitCode, Error> {
+    let mut events = Vec::new();
+    let (mut passed, mut failed, mut tle) = (0, 0, false);
+    for (i, case) in cases.iter().enumerate() {
+        let input = base64::decode(&case.input)?;
+        let expected = case.expected.as_ref().map(base64::decode).transpose()?;
+        let output = match client.codetest(lang, code, Some(&input)).await? {
+            CodetestResult::Ran(output) => output,
+            result => return codetest_failure(result, format),
+        };
+        let ok = expected.as_ref().map(|e| same_output(e, &output.stdout));
+        match ok {
+            Some(true) => passed += 1,
+            Some(false) => failed += 1,
+            None => (),
+        }
+        tle |= output.killed;
+        let event = with_output(
+            json!({
+                "type": "codetest_case",
+                "name": case.name,
+                "passed": ok,
+                "time": output.time,
+                "killed": output.killed,
+                "exit_status": output.exit_status,
+            }),
+            &output.stdout,
+            &output.stderr,
+        );
+        print_event(format, &event);
+        events.push(event);
+        if format != OutputFormat::Text {
+            continue;
+        }
+        if i != 0 {
+            println!();
+        }
+        match ok {
+            Some(true) => println!(
+                "{}: {}",
+                palette.bold(&case.name),
+                palette.fg(palette.ac, Msg::CasePassed)
             ),
-            JudgeStatus::Re(t) => write!(
-                f,
-                "{}{}RE{}{}  {t: >7} ms",
-                style::Bold,
-                color::Fg(color::Yellow),
-                color::Fg(color::Reset),
-                style::Reset,
+            Some(false) => println!(
+                "{}: {}",
+                palette.bold(&case.name),
+                palette.fg(palette.wa, Msg::CaseFailed)
             ),
-            JudgeStatus::Wj => write!(f, "..."),
+            None => println!("{}", palette.bold(&case.name)),
+        }
+        if output.killed {
+            println!("{}", palette.fg(palette.tle, Msg::CodetestTle));
+        }
+        println!("{}", Msg::CodetestTime(output.time));
+        println!("{}", Msg::CodetestExitStatus(&output.exit_status));
+        let stdout = match &expected {
+            Some(e) if ok == Some(false) => {
+                print!("{}", format_diff(e, &output.stdout, 10, palette));
+                &[][..]
+            }
+            Some(_) => &[],
+            None => &output.stdout,
+        };
+        write_output(stdout, &output.stderr)?;
+    }
+    if format == OutputFormat::Text && passed + failed != 0 {
+        println!(
+            "\n{}",
+            palette.bold(Msg::CasesSummary(passed, passed + failed))
+        );
+    }
+    print_summary(
+        format,
+        &json!({
+            "type": "codetest_cases",
+            "passed": passed,
+            "failed": failed,
+            "cases": events,
+        }),
+    );
+    Ok(if tle {
+        ExitCode::Tle
+    } else if failed != 0 {
+        ExitCode::Wa
+    } else {
+        ExitCode::Success
+    })
+}
+
+fn print_event(format: OutputFormat, event: &serde_json::Value) {
+    if let Some(line) = format.event(event) {
+        println!("{line}");
+    }
+}
+
+fn print_summary(format: OutputFormat, summary: &serde_json::Value) {
+    if let Some(summary) = format.summary(summary) {
+        println!("{summary}");
+    }
+}
+
+fn print_table(rows: &[Vec<String>], right_aligned: &[usize]) {
+    for line in format_table(rows, right_aligned) {
+        println!("{line}");
+    }
+}
+
+/// Compares outputs, ignoring a newline at the end of either.
+fn same_output(expected: &[u8], actual: &[u8]) -> bool {
+    expected.strip_suffix(b"\n").unwrap_or(expected) == actual.strip_suffix(b"\n").unwrap_or(actual)
+}
+
+/// Formats `bytes` like `hexdump -C`.
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex = chunk.iter().map(|b| format!("{b:02x}")).join(" ");
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {hex:<47}  |{ascii}|\n", i * 16)
+        })
+        .collect()
+}
+
+fn current_user_name() -> Result<String, Error> {
+    let uid = get_current_uid();
+    let user = get_user_by_uid(uid).ok_or(Error::UnknownUser(uid))?;
+    Ok(user.name().to_string_lossy().to_string())
+}
+
+async fn connect(config: &Config) -> Result<Client, Error> {
+    Client::connect(config, |retry, e| {
+        eprintln!("{}", Msg::Retrying(e, retry, config.connect_retries))
+    })
+    .await
+}
+
+/// Waits for `judging` until `judge_timeout`, then fails with [`Error::Timeout`].
+async fn with_deadline<T>(
+    config: &Config,
+    judging: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    tokio::time::timeout(config.judge_timeout, judging)
+        .await
+        .unwrap_or(Err(Error::Timeout(config.judge_timeout.as_secs())))
+}
+
+/// When the job ran out of time, erases the progress `drawn` so far and asks the
+/// server to stop the job before reporting the timeout.
+async fn give_up_on_timeout<T>(
+    result: Result<T, Error>,
+    client: &mut Client,
+    drawn: &str,
+) -> Result<T, Error> {
+    if let Err(Error::Timeout(_)) = result {
+        print!("{}", Erase(drawn));
+        std::io::stdout().flush().unwrap_or_default();
+        client.cancel().await;
+    }
+    result
+}
+
+/// Reads the command `mmagolf` wrote to stdin.
+fn read_input() -> Result<Command, Error> {
+    serde_json::from_reader(std::io::stdin().lock()).map_err(Error::InvalidCommand)
+}
+
+/// Tells why the judge stopped if it did not finish.
+fn explain_stop(result: &JudgeResult) {
+    match result {
+        JudgeResult::Disconnected => eprintln!("{}", Msg::JudgeInterrupted),
+        JudgeResult::Cancelled { acknowledged: true } => eprintln!("{}", Msg::Cancelled),
+        JudgeResult::Cancelled {
+            acknowledged: false,
+        } => {
+            eprintln!("{}", Msg::CancelUnacknowledged)
         }
+        _ => (),
     }
 }
 
-fn statuses_to_string<'a>(
-    judge_statuses: &'a HashMap<&'a String, JudgeStatus>,
-    test_case_number: &HashMap<&'a String, usize>,
-    n: usize,
-) -> String {
-    judge_statuses
+/// Completes on the first Ctrl-C.
+async fn ctrl_c() {
+    if signal::ctrl_c().await.is_err() {
+        // Never cancel if the signal cannot be caught.
+        future::pending().await
+    }
+}
+
+/// Where an accepted submission landed in the ranking.
+struct Placement {
+    /// 1-based
+    rank: usize,
+    shortest: bool,
+    language_shortest: bool,
+}
+
+fn summary(result: &JudgeResult, size: usize, placement: Option<Placement>) -> serde_json::Value {
+    let (verdict, time, test_cases) = match result {
+        JudgeResult::Judged(status, test_cases) => (
+            status.name(),
+            status.time(),
+            test_cases
+                .iter()
+                .map(|(name, s)| json!({ "name": name, "verdict": s.name(), "time": s.time() }))
+                .collect(),
+        ),
+        JudgeResult::CompileError(_) => ("CE", None, Vec::new()),
+        JudgeResult::NotSuchProblem(_) => ("not_such_problem", None, Vec::new()),
+        JudgeResult::NotSuchLang(_) => ("not_such_lang", None, Vec::new()),
+        JudgeResult::Disconnected => ("disconnected", None, Vec::new()),
+        JudgeResult::Cancelled { .. } => ("cancelled", None, Vec::new()),
+    };
+    let mut summary = json!({
+        "type": "summary",
+        "verdict": verdict,
+        "time": time,
+        "size": size,
+        "test_cases": test_cases,
+        "rank": placement.as_ref().map(|p| p.rank),
+        "shortest": placement.as_ref().is_some_and(|p| p.shortest),
+        "language_shortest": placement.as_ref().is_some_and(|p| p.language_shortest),
+    });
+    match result {
+        JudgeResult::CompileError(e) => {
+            if let serde_json::Value::Object(fields) = compile_error_event(e) {
+                for (key, value) in fields.into_iter().filter(|(key, _)| key != "type") {
+                    summary[key] = value;
+                }
+            }
+        }
+        JudgeResult::NotSuchProblem(problem) => summary["problem"] = problem.as_str().into(),
+        JudgeResult::NotSuchLang(lang) => summary["lang"] = lang.as_str().into(),
+        _ => (),
+    }
+    summary
+}
+
+/// `n` dots for test cases not judged yet
+fn statuses_to_string(test_cases: &[(String, JudgeStatus)], n: usize, palette: &Palette) -> String {
+    test_cases
         .iter()
-        .sorted_unstable_by_key(|(name, _)| test_case_number[**name])
         .map(|(name, s)| {
             if *s == JudgeStatus::Wj {
                 format!(
@@ -213,182 +686,429 @@ fn statuses_to_string<'a>(
                     iter::once(".").cycle().take(n).collect::<String>()
                 )
             } else {
-                format!("{name}: {s}\n")
+                format!("{name}: {}\n", s.paint(palette))
             }
         })
         .collect()
 }
 
-fn overall_result(judge_statuses: &HashMap<&String, JudgeStatus>) -> JudgeStatus {
-    let mut ac = false;
-    let mut tle = false;
-    let mut wa = false;
-    let mut re = false;
-    let mut time = 0;
-    for (_, s) in judge_statuses {
-        match *s {
-            JudgeStatus::Ac(t) => {
-                ac = true;
-                time = time.max(t);
-            }
-            JudgeStatus::Tle(t) => {
-                tle = true;
-                time = time.max(t);
+/// Shows the judge as `progress` says. `drawn` keeps the progress on the screen
+/// so that the caller can erase it when it gives up.
+async fn display_result(
+    events: impl Stream<Item = Result<JudgeEvent, Error>>,
+    size: usize,
+    format: OutputFormat,
+    progress: Progress,
+    palette: &Palette,
+    drawn: &mut String,
+) -> Result<JudgeResult, Error> {
+    pin_mut!(events);
+    if format != OutputFormat::Text {
+        return report_result(events, |e| {
+            if let Some(e) = event_json(e) {
+                print_event(format, &e)
             }
-            JudgeStatus::Wa(t) => {
-                wa = true;
-                time = time.max(t);
-            }
-            JudgeStatus::Re(t) => {
-                re = true;
-                time = time.max(t);
+            Ok(())
+        })
+        .await;
+    }
+    if progress != Progress::Fancy {
+        let show_test_cases = progress == Progress::Plain;
+        let result = report_result(events, |e| plain_event(e, show_test_cases, palette)).await?;
+        if let JudgeResult::Judged(status, _) = &result {
+            println!("{}", Msg::Result(&status.paint(palette), size));
+        }
+        return Ok(result);
+    }
+    let mut test_cases = match events.next().await {
+        Some(Ok(JudgeEvent::TestCaseNames(names))) => waiting(names),
+        Some(Ok(JudgeEvent::Finished(result))) => {
+            display_failure(&result)?;
+            return Ok(result);
+        }
+        Some(Err(e)) => return Err(e),
+        // The client reports a result of an unknown test case as an error.
+        Some(Ok(JudgeEvent::TestCaseResult { .. })) | None => unreachable!(),
+    };
+    let mut dots = (0..4).cycle();
+    let mut redraw = interval(Duration::from_millis(200));
+    let result = loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(Ok(JudgeEvent::TestCaseNames(names))) => test_cases = waiting(names),
+                Some(Ok(JudgeEvent::TestCaseResult { name, status })) => {
+                    if let Some(case) = test_cases.iter_mut().find(|(n, _)| *n == name) {
+                        case.1 = status;
+                    }
+                }
+                Some(Ok(JudgeEvent::Finished(result))) => break result,
+                Some(Err(e)) => return Err(e),
+                None => unreachable!(),
+            },
+            _ = redraw.tick() => {
+                let s = statuses_to_string(&test_cases, dots.next().unwrap(), palette);
+                print!("{}{}", Erase(drawn), s);
+                *drawn = s;
             }
-            JudgeStatus::Wj => panic!(),
         }
+    };
+    print!("{}", Erase(&std::mem::take(drawn)));
+    match &result {
+        JudgeResult::Judged(status, _) => {
+            print!("{}", statuses_to_string(&test_cases, 3, palette));
+            println!("\n{}", Msg::Result(&status.paint(palette), size));
+        }
+        JudgeResult::Disconnected | JudgeResult::Cancelled { .. } => {
+            print!("{}", statuses_to_string(&test_cases, 3, palette));
+        }
+        _ => display_failure(&result)?,
     }
-    if tle {
-        JudgeStatus::Tle(time)
-    } else if re {
-        JudgeStatus::Re(time)
-    } else if wa {
-        JudgeStatus::Wa(time)
-    } else if ac {
-        JudgeStatus::Ac(time)
-    } else {
-        panic!()
+    Ok(result)
+}
+
+fn waiting(test_case_names: Vec<String>) -> Vec<(String, JudgeStatus)> {
+    test_case_names
+        .into_iter()
+        .map(|n| (n, JudgeStatus::Wj))
+        .collect()
+}
+
+/// Receives results without redrawing, calling `on_event` for each event.
+async fn report_result(
+    mut events: impl Stream<Item = Result<JudgeEvent, Error>> + Unpin,
+    mut on_event: impl FnMut(&JudgeEvent) -> Result<(), Error>,
+) -> Result<JudgeResult, Error> {
+    while let Some(event) = events.next().await {
+        let event = event?;
+        on_event(&event)?;
+        if let JudgeEvent::Finished(result) = event {
+            return Ok(result);
+        }
     }
+    Ok(JudgeResult::Disconnected)
 }
 
-async fn display_result(mut receiver: Receiver<ReternMessage>, size: usize) -> Option<JudgeStatus> {
-    let test_case_names = match receiver.recv().await {
-        Some(ReternMessage::TestCaseNames { ns }) => ns,
-        Some(ReternMessage::NotSuchProblem { problem_name }) => {
-            println!("Not such problem: {problem_name}");
-            return None;
+/// Append-only text output for `Progress::Plain` and `Progress::None`.
+fn plain_event(event: &JudgeEvent, show_test_cases: bool, palette: &Palette) -> Result<(), Error> {
+    match event {
+        JudgeEvent::TestCaseResult { name, status } if show_test_cases => {
+            println!("{}: {}", name, status.paint(palette));
         }
-        Some(ReternMessage::NotSuchLang { lang }) => {
-            println!("Not such language: {lang}");
-            return None;
+        JudgeEvent::Finished(result) => display_failure(result)?,
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Shows a judge that ended before running the code.
+fn display_failure(result: &JudgeResult) -> Result<(), Error> {
+    match result {
+        JudgeResult::CompileError(e) => display_compile_error(e)?,
+        JudgeResult::NotSuchProblem(problem_name) => {
+            println!("{}", Msg::NotSuchProblem(problem_name))
         }
-        r => panic!("{:?}", r),
-    };
-    let test_case_number: HashMap<&String, usize> = test_case_names
-        .iter()
-        .enumerate()
-        .map(|(i, n)| (n, i))
-        .collect();
-    let mut judge_statuses: HashMap<&String, JudgeStatus> = test_case_names
-        .iter()
-        .map(|name| (name, JudgeStatus::Wj))
-        .collect();
-    let mut old = String::new();
-    for i in (0..4).cycle() {
-        match receiver.try_recv() {
-            Ok(ReternMessage::SubmissionResult {
-                test_case_name,
-                result,
-                time,
-                killed,
-            }) => {
-                *judge_statuses.get_mut(&test_case_name).unwrap() = if killed {
-                    JudgeStatus::Tle(time)
-                } else {
-                    match result {
-                        SubmissionResultType::Ac => JudgeStatus::Ac(time),
-                        SubmissionResultType::Re => JudgeStatus::Re(time),
-                        SubmissionResultType::Wa => JudgeStatus::Wa(time),
-                    }
-                };
+        JudgeResult::NotSuchLang(lang) => println!("{}", Msg::NotSuchLang(lang)),
+        _ => (),
+    }
+    Ok(())
+}
+
+fn event_json(event: &JudgeEvent) -> Option<serde_json::Value> {
+    Some(match event {
+        JudgeEvent::TestCaseNames(names) => json!({ "type": "test_case_names", "names": names }),
+        JudgeEvent::TestCaseResult { name, status } => json!({
+            "type": "test_case_result",
+            "name": name,
+            "verdict": status.name(),
+            "time": status.time(),
+        }),
+        JudgeEvent::Finished(JudgeResult::CompileError(e)) => compile_error_event(e),
+        JudgeEvent::Finished(JudgeResult::NotSuchProblem(problem_name)) => {
+            json!({ "type": "not_such_problem", "problem_name": problem_name })
+        }
+        JudgeEvent::Finished(JudgeResult::NotSuchLang(lang)) => {
+            json!({ "type": "not_such_lang", "lang": lang })
+        }
+        JudgeEvent::Finished(_) => return None,
+    })
+}
+
+fn compile_error_event(e: &CompileError) -> serde_json::Value {
+    with_output(
+        json!({
+            "type": "compile_error",
+            "exit_code": e.exit_code,
+        }),
+        &e.stdout,
+        &e.stderr,
+    )
+}
+
+/// Adds `stdout` and `stderr` as text, and as base64 in `stdout_base64` and
+/// `stderr_base64` for output that is not UTF-8.
+fn with_output(mut value: serde_json::Value, stdout: &[u8], stderr: &[u8]) -> serde_json::Value {
+    value["stdout"] = String::from_utf8_lossy(stdout).into();
+    value["stderr"] = String::from_utf8_lossy(stderr).into();
+    value["stdout_base64"] = base64::encode(stdout).into();
+    value["stderr_base64"] = base64::encode(stderr).into();
+    value
+}
+
+fn display_compile_error(e: &CompileError) -> Result<(), Error> {
+    let mut output = format!("{}\n{}\n", Msg::CompileError(e.exit_code), Msg::Stdout).into_bytes();
+    output.extend_from_slice(&e.stdout);
+    output.extend_from_slice(format!("{}\n", Msg::Stderr).as_bytes());
+    output.extend_from_slice(&e.stderr);
+    write_output(&output, &[])
+}
+
+/// Writes what the code printed as is, which may not be UTF-8.
+fn write_output(stdout: &[u8], stderr: &[u8]) -> Result<(), Error> {
+    let mut out = std::io::stdout();
+    out.write_all(stdout).map_err(Error::Output)?;
+    out.flush().map_err(Error::Output)?;
+    std::io::stderr().write_all(stderr).map_err(Error::Output)
+}
+
+fn display_problems(problems: Vec<Problem>, problem_name: Option<&str>, palette: &Palette) {
+    match problem_name {
+        None => {
+            let rows: Vec<_> = iter::once(
+                ["name", "title", "test cases", "time limit"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .chain(problems.iter().map(|p| {
+                vec![
+                    p.name.clone(),
+                    p.title.clone(),
+                    p.test_case_names.len().to_string(),
+                    format!("{} ms", p.time_limit),
+                ]
+            }))
+            .collect();
+            print_table(&rows, &[2]);
+        }
+        Some(name) => match problems.iter().find(|p| p.name == name) {
+            Some(p) => {
+                println!("{}: {}", palette.bold(&p.name), p.title);
+                print!("time limit: {} ms", p.time_limit);
+                if let Some(m) = p.memory_limit {
+                    print!(", memory limit: {m} MB");
+                }
+                println!("\ntest cases: {}\n", p.test_case_names.join(", "));
+                println!("{}", p.statement.trim_end());
             }
-            Ok(ReternMessage::Close) => {
-                break;
+            None => println!("{}", Msg::NotSuchProblem(name)),
+        },
+    }
+}
+
+fn display_langs(langs: &[Lang]) {
+    let rows: Vec<_> = iter::once(
+        ["id", "name", "version", "compile", "run"]
+            .map(String::from)
+            .to_vec(),
+    )
+    .chain(langs.iter().map(|l| {
+        vec![
+            l.id.clone(),
+            l.name.clone(),
+            l.version.clone(),
+            l.compile.clone().unwrap_or_else(|| "-".to_string()),
+            l.run.clone(),
+        ]
+    }))
+    .collect();
+    print_table(&rows, &[]);
+}
+
+fn display_ranking(
+    submissions: Vec<Submission>,
+    problem_name: Option<&str>,
+    top: usize,
+    by_lang: bool,
+    palette: &Palette,
+) {
+    let (problems, _) = rank_submissions(submissions);
+    let names: Vec<&String> = match problem_name {
+        Some(name) => match problems.get_key_value(name) {
+            Some((name, _)) => vec![name],
+            None => {
+                println!("{}", Msg::NoSubmissionsFor(name));
+                return;
             }
-            Ok(ReternMessage::CompileError {
-                code,
-                stdout,
-                stderr,
-            }) => {
-                print!("{}", Erase(&old));
-                display_compile_error(code, stdout, stderr).await;
-                return None;
+        },
+        None => problems.keys().sorted().collect(),
+    };
+    for (i, name) in names.into_iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        println!("{}", palette.bold(name));
+        let mut langs = HashSet::new();
+        let rows: Vec<_> = problems[name]
+            .iter()
+            .filter(|s| !by_lang || langs.insert(&s.lang))
+            .take(top)
+            .enumerate()
+            .map(|(rank, s)| {
+                let time: DateTime<Local> = DateTime::from(s.time);
+                vec![
+                    (rank + 1).to_string(),
+                    format!("{} B", s.size),
+                    s.lang.clone(),
+                    s.user.clone(),
+                    time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                ]
+            })
+            .collect();
+        for (rank, line) in format_table(&rows, &[0, 1]).into_iter().enumerate() {
+            if rank == 0 {
+                println!("{}", palette.bold(palette.fg(color::GREEN, line)));
+            } else {
+                println!("{line}");
             }
-            _ => (),
         }
-        let s = statuses_to_string(&judge_statuses, &test_case_number, i);
-        print!("{}{}", Erase(&old), s);
-        old = s;
-        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
     }
-    let result = overall_result(&judge_statuses);
-    println!("\nResult: {}, {} B", result, size);
-    Some(result)
+}
+
+/// Users ordered by the number of problems where they hold the shortest code,
+/// then by the number of per-language shortests, then by solved problems.
+fn display_leaderboard(submissions: Vec<Submission>, palette: &Palette) {
+    #[derive(Default)]
+    struct Score {
+        shortest: usize,
+        lang_shortest: usize,
+        solved: usize,
+    }
+    let mut scores: HashMap<String, Score> = HashMap::new();
+    for (user, _) in submissions.iter().map(|s| (&s.user, &s.problem)).unique() {
+        scores.entry(user.clone()).or_default().solved += 1;
+    }
+    let (problems, _) = rank_submissions(submissions);
+    for ranking in problems.values() {
+        scores.get_mut(&ranking[0].user).unwrap().shortest += 1;
+        for s in ranking.iter().unique_by(|s| &s.lang) {
+            scores.get_mut(&s.user).unwrap().lang_shortest += 1;
+        }
+    }
+    let rows: Vec<_> = iter::once(
+        ["rank", "user", "shortest", "lang shortest", "solved"]
+            .map(String::from)
+            .to_vec(),
+    )
+    .chain(
+        scores
+            .iter()
+            .sorted_by_key(|(user, s)| {
+                (
+                    std::cmp::Reverse((s.shortest, s.lang_shortest, s.solved)),
+                    *user,
+                )
+            })
+            .enumerate()
+            .map(|(rank, (user, s))| {
+                vec![
+                    (rank + 1).to_string(),
+                    user.clone(),
+                    s.shortest.to_string(),
+                    s.lang_shortest.to_string(),
+                    s.solved.to_string(),
+                ]
+            }),
+    )
+    .collect();
+    for (i, line) in format_table(&rows, &[0, 2, 3, 4]).into_iter().enumerate() {
+        match i {
+            0 => println!("{}", palette.bold(line)),
+            1 => println!("{}", palette.bold(palette.fg(color::GREEN, line))),
+            _ => println!("{line}"),
+        }
+    }
 }
 
 const HOME_DIR: &str = env!("HOME");
 
-async fn get_submission_list() -> (
-    HashMap<String, Vec<Submission>>,
-    usize,
-    FileLock,
-    HashMap<(String, String), usize>,
-) {
-    let data_dir = std::path::Path::new(HOME_DIR).join(".local/share/mmagolf");
-    fs::create_dir_all(&data_dir).await.unwrap();
+async fn get_submission_list(
+    data_dir: &Path,
+) -> Result<
+    (
+        HashMap<String, Vec<Submission>>,
+        usize,
+        FileLock,
+        LanguageShortests,
+    ),
+    Error,
+> {
+    fs::create_dir_all(data_dir)
+        .await
+        .map_err(|e| Error::Io(data_dir.to_path_buf(), e))?;
+    let path = data_dir.join("submissions");
     let file = FileOptions::new().append(true).create(true).read(true);
-    let mut file = task::spawn_blocking(move || {
-        FileLock::lock(data_dir.join("submissions"), true, file).unwrap()
-    })
-    .await
-    .unwrap();
-    let (file, s) = task::spawn_blocking(move || {
+    let (file, s) = task::block_in_place(|| {
+        let mut file = FileLock::lock(&path, true, file).map_err(|e| Error::Io(path.clone(), e))?;
         let mut s = String::new();
-        file.file.read_to_string(&mut s).unwrap();
-        (file, s)
-    })
-    .await
-    .unwrap();
-    let submissions: Vec<_> = s.lines().collect();
-    let mut language_shortest: HashMap<(String, String), usize> = HashMap::new();
+        file.file
+            .read_to_string(&mut s)
+            .map_err(|e| Error::Io(path, e))?;
+        Ok::<_, Error>((file, s))
+    })?;
+    let submissions = parse_submissions(&s)?;
     let total_submission_number = submissions.len();
-    let mut submissions: Vec<_> = submissions
-        .into_iter()
-        .enumerate()
-        .map(|(i, l)| Submission::from_str(l, i).unwrap())
-        .filter(|submission| {
-            let shortest = language_shortest
-                .get(&(submission.problem.to_string(), submission.lang.clone()))
-                .copied()
-                .unwrap_or(usize::MAX);
-            if submission.size < shortest {
-                language_shortest.insert(
-                    (submission.problem.to_string(), submission.lang.clone()),
-                    submission.size,
-                );
-                true
-            } else {
-                false
+    let (problems, language_shortest) = rank_submissions(submissions);
+    Ok((problems, total_submission_number, file, language_shortest))
+}
+
+/// Reads the submissions log without creating it.
+async fn read_submission_log(data_dir: &Path) -> Result<String, Error> {
+    let path = data_dir.join("submissions");
+    task::block_in_place(
+        || match FileLock::lock(&path, true, FileOptions::new().read(true)) {
+            Ok(mut file) => {
+                let mut s = String::new();
+                file.file
+                    .read_to_string(&mut s)
+                    .map_err(|e| Error::Io(path, e))?;
+                Ok(s)
             }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(Error::Io(path, e)),
+        },
+    )
+}
+
+fn parse_submissions(s: &str) -> Result<Vec<Submission>, Error> {
+    s.lines()
+        .enumerate()
+        .map(|(i, l)| {
+            Submission::from_str(l, i).ok_or_else(|| Error::InvalidSubmission(i + 1, l.to_string()))
         })
-        .collect();
-    submissions.sort_unstable_by_key(|s| (s.size, s.id));
-    let mut problems: HashMap<String, Vec<Submission>> = HashMap::new();
-    for s in submissions {
-        problems.entry(s.problem.clone()).or_default().push(s);
-    }
-    (problems, total_submission_number, file, language_shortest)
+        .collect()
 }
 
-async fn save_submission(code: &str, n: usize) {
-    let submitted_files =
-        std::path::Path::new(HOME_DIR).join(".local/share/mmagolf/submitted_files");
-    fs::create_dir_all(&submitted_files).await.unwrap();
+async fn save_submission(data_dir: &Path, code: &[u8], n: usize) -> Result<(), Error> {
+    let submitted_files = data_dir.join("submitted_files");
+    fs::create_dir_all(&submitted_files)
+        .await
+        .map_err(|e| Error::Io(submitted_files.clone(), e))?;
+    let path = submitted_files.join(n.to_string());
     let mut file = OpenOptions::new()
         .create_new(true)
         .write(true)
-        .open(submitted_files.join(n.to_string()))
+        .open(&path)
         .await
-        .unwrap();
-    file.write_all(code.as_bytes()).await.unwrap();
+        .map_err(|e| Error::Io(path.clone(), e))?;
+    file.write_all(code).await.map_err(|e| Error::Io(path, e))
+}
+
+/// Reads the code of submission `id` from `submitted_files`.
+async fn read_code(submitted_files: &Path, id: usize) -> Result<Vec<u8>, Error> {
+    let path = submitted_files.join(id.to_string());
+    fs::read(&path).await.map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Error::MissingSubmittedFile(id),
+        _ => Error::Io(path, e),
+    })
 }
 
 const RANK_LEN: usize = 10;
@@ -407,38 +1127,38 @@ fn insert_submission(
         (i, submissions)
     } else {
         submissions.insert(new_submission.problem.clone(), vec![new_submission]);
-        (1, submissions)
+        (0, submissions)
     }
 }
 
 struct SubmittedFiles {
     path: PathBuf,
-    catch: HashMap<usize, String>,
+    catch: HashMap<usize, Vec<u8>>,
 }
 
 impl SubmittedFiles {
-    fn new(new_submission_id: usize, new_submission_code: String) -> SubmittedFiles {
+    fn new(
+        data_dir: &Path,
+        new_submission_id: usize,
+        new_submission_code: Vec<u8>,
+    ) -> SubmittedFiles {
         let mut catch = HashMap::new();
         catch.insert(new_submission_id, new_submission_code);
         SubmittedFiles {
-            path: std::path::Path::new(HOME_DIR).join(".local/share/mmagolf/submitted_files"),
+            path: data_dir.join("submitted_files"),
             catch,
         }
     }
 
-    async fn get(&mut self, id: usize) -> &str {
-        if self.catch.contains_key(&id) {
-            &self.catch[&id]
-        } else {
-            let code = fs::read_to_string(self.path.join(id.to_string()))
-                .await
-                .unwrap();
+    async fn get(&mut self, id: usize) -> Result<&[u8], Error> {
+        if !self.catch.contains_key(&id) {
+            let code = read_code(&self.path, id).await?;
             self.catch.insert(id, code);
-            &self.catch[&id]
         }
+        Ok(&self.catch[&id])
     }
 
-    fn get_from_catch(&self, id: usize) -> Option<&str> {
+    fn get_from_catch(&self, id: usize) -> Option<&[u8]> {
         self.catch.get(&id).map(|s| &s[..])
     }
 }
@@ -448,53 +1168,49 @@ struct FileSender {
 }
 
 impl FileSender {
-    fn new() -> Self {
-        let tcp = TcpStream::connect("webserver.lxd.saga.mma.club.uec.ac.jp:22").unwrap();
-        let mut sesstion = ssh2::Session::new().unwrap();
+    fn new() -> Result<Self, Error> {
+        let tcp =
+            TcpStream::connect("webserver.lxd.saga.mma.club.uec.ac.jp:22").map_err(Error::Ssh)?;
+        let mut sesstion = ssh2::Session::new()?;
         sesstion.set_tcp_stream(tcp);
-        sesstion.handshake().unwrap();
-        sesstion
-            .userauth_pubkey_file(
-                "mado",
-                None,
-                &Path::new(HOME_DIR).join(".ssh/id_ed25519_web"),
-                None,
-            )
-            .unwrap();
-        FileSender { sesstion }
+        sesstion.handshake()?;
+        sesstion.userauth_pubkey_file(
+            "mado",
+            None,
+            &Path::new(HOME_DIR).join(".ssh/id_ed25519_web"),
+            None,
+        )?;
+        Ok(FileSender { sesstion })
     }
 
-    fn send(&self, remote_path: &Path, contents: String) {
+    fn send(&self, remote_path: &Path, contents: String) -> Result<(), Error> {
         let mut f = self
             .sesstion
-            .scp_send(remote_path, 0o644, contents.len() as u64, None)
-            .unwrap();
-        f.write_all(contents.as_bytes()).unwrap();
-        f.send_eof().unwrap();
-        f.wait_eof().unwrap();
-        f.close().unwrap();
-        f.wait_close().unwrap();
+            .scp_send(remote_path, 0o644, contents.len() as u64, None)?;
+        f.write_all(contents.as_bytes()).map_err(Error::Ssh)?;
+        f.send_eof()?;
+        f.wait_eof()?;
+        f.close()?;
+        f.wait_close()?;
+        Ok(())
     }
 }
 
 async fn make_ranking(
     submissions: &HashMap<String, Vec<Submission>>,
     new_submission_rank: usize,
-    submitted_files: SubmittedFiles,
-) {
+    mut submitted_files: SubmittedFiles,
+) -> Result<(), Error> {
     if new_submission_rank >= RANK_LEN {
-        return;
+        return Ok(());
+    }
+    for s in submissions.values().flatten() {
+        submitted_files.get(s.id).await?;
     }
-    let submitted_files = stream::iter(submissions.iter().map(|(_, v)| v).flatten())
-        .fold(submitted_files, |mut f, s| async {
-            f.get(s.id).await;
-            f
-        })
-        .await;
     let s: serde_json::Map<String, _> = join_all(submissions.iter().map(|(id, p)| async {
         let ss = join_all(p.iter().take(RANK_LEN).map(|s| async {
             let code = submitted_files.get_from_catch(s.id).unwrap();
-            let code = htmlescape::encode_minimal(code);
+            let code = htmlescape::encode_minimal(&printable(code));
             let time: DateTime<Local> = DateTime::from(s.time);
             [
                 s.size.to_string(),
@@ -513,27 +1229,65 @@ async fn make_ranking(
     #[cfg(feature = "dry_run")]
     println!("{}", s);
     #[cfg(not(feature = "dry_run"))]
-    FileSender::new().send(Path::new("/home/mado/public_html/golf/ranking.json"), s);
+    FileSender::new()?.send(Path::new("/home/mado/public_html/golf/ranking.json"), s)?;
+    Ok(())
 }
 
 #[cfg(not(feature = "dry_run"))]
 const WEBHOOK_URL: &str = include_str!("webhook_url");
 
-fn shortest(submission: &Submission, code: &str, dry_run: bool) {
+/// Posts the new shortest to Slack in `locale`, the same for everyone in the channel.
+fn shortest(
+    submission: &Submission,
+    code: &[u8],
+    dry_run: bool,
+    locale: Locale,
+) -> Result<(), Error> {
     #[cfg(not(feature = "dry_run"))]
     if !dry_run {
-        let slack = Slack::new(WEBHOOK_URL).unwrap();
+        let slack = Slack::new(WEBHOOK_URL)?;
         let p = PayloadBuilder::new()
-            .text(format!(
-                "{} が {} で {} のShortestを更新しました！（{} B）\n```{}```",
-                submission.user, submission.lang, submission.problem, submission.size, code
-            ))
-            .username("Shortest更新通知")
+            .text(
+                Msg::ShortestNotification(
+                    &submission.user,
+                    &submission.lang,
+                    &submission.problem,
+                    submission.size,
+                    &printable(code),
+                )
+                .to_string_in(locale),
+            )
+            .username(Msg::ShortestNotificationName.to_string_in(locale))
             .icon_emoji(":golf:")
             .channel("#shortest更新通知")
-            .build()
-            .unwrap();
-        slack.send(&p).unwrap();
+            .build()?;
+        slack.send(&p)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_newline_is_ignored() {
+        assert!(same_output(b"1\n2\n", b"1\n2\n"));
+        assert!(same_output(b"1\n2\n", b"1\n2"));
+        assert!(same_output(b"1\n2", b"1\n2\n"));
+        assert!(same_output(b"", b"\n"));
+    }
+
+    #[test]
+    fn only_one_trailing_newline_is_ignored() {
+        assert!(!same_output(b"1\n\n", b"1"));
+        assert!(!same_output(b"1\n", b"1\r\n"));
+        assert!(!same_output(b"1 \n", b"1\n"));
+    }
+
+    #[test]
+    fn different_output() {
+        assert!(!same_output(b"1\n2\n", b"1\n3\n"));
+        assert!(!same_output(b"\n1", b"1"));
     }
-    println!("Shortest! 🎉");
 }
@@ -0,0 +1,614 @@
+//! Talking to the judge server.
+//!
+//! Nothing here prints. Results come back as values for the binaries to show.
+
+use crate::{config::Config, Error, ExitCode, JudgeStatus, Lang, Problem};
+use futures_util::{stream, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{future::Future, io, pin::Pin, time::Duration};
+use tokio::{
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, protocol::Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all(deserialize = "snake_case"))]
+enum ReternMessage {
+    SubmissionResult {
+        test_case_name: String,
+        result: SubmissionResultType,
+        time: u64,
+        killed: bool,
+    },
+    CompileError {
+        code: i32,
+        stdout: String,
+        stderr: String,
+    },
+    CodetestResult {
+        stdout: String,
+        stderr: String,
+        time: u64,
+        killed: bool,
+        status: String,
+    },
+    TestCaseNames {
+        ns: Vec<String>,
+    },
+    NotSuchProblem {
+        problem_name: String,
+    },
+    NotSuchLang {
+        lang: String,
+    },
+    Problems {
+        problems: Vec<Problem>,
+    },
+    Langs {
+        langs: Vec<Lang>,
+    },
+    /// the reply to a `cancel` request
+    Cancelled,
+    /// the reply to the handshake
+    Hello {
+        version: u32,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+    },
+}
+
+/// The version of the protocol this client speaks. Bump it on any change
+/// that older clients or servers would misread.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features of the protocol, declared by both sides in the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// the server stops a job on a `cancel` request
+    Cancellation,
+    /// source code may be sent as base64 in `code_base64` instead of text in `code`,
+    /// so that it does not have to be UTF-8
+    BinarySources,
+    /// a capability this client does not know
+    #[serde(other)]
+    Unknown,
+}
+
+/// What this client understands.
+const CAPABILITIES: &[Capability] = &[Capability::Cancellation, Capability::BinarySources];
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "snake_case"))]
+enum SubmissionResultType {
+    Ac,
+    Re,
+    Wa,
+}
+
+/// What happens while code is judged. The last event is always `Finished`.
+#[derive(Debug)]
+pub enum JudgeEvent {
+    TestCaseNames(Vec<String>),
+    TestCaseResult { name: String, status: JudgeStatus },
+    Finished(JudgeResult),
+}
+
+#[derive(Debug)]
+pub enum JudgeResult {
+    /// the overall status and the status of each test case in order
+    Judged(JudgeStatus, Vec<(String, JudgeStatus)>),
+    CompileError(CompileError),
+    NotSuchProblem(String),
+    NotSuchLang(String),
+    /// the server stopped before judging every test case
+    Disconnected,
+    /// `acknowledged` is false if the server did not confirm in time,
+    /// so the job may still be running
+    Cancelled {
+        acknowledged: bool,
+    },
+}
+
+impl JudgeResult {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            JudgeResult::Judged(JudgeStatus::Ac(_), _) => ExitCode::Success,
+            JudgeResult::Judged(JudgeStatus::Wa(_), _) => ExitCode::Wa,
+            JudgeResult::Judged(JudgeStatus::Re(_), _) => ExitCode::Re,
+            JudgeResult::Judged(JudgeStatus::Tle(_), _) => ExitCode::Tle,
+            JudgeResult::Judged(JudgeStatus::Wj, _) => ExitCode::Failure,
+            JudgeResult::CompileError(_) => ExitCode::CompileError,
+            JudgeResult::NotSuchProblem(_) => ExitCode::NotSuchProblem,
+            JudgeResult::NotSuchLang(_) => ExitCode::NotSuchLang,
+            JudgeResult::Disconnected => ExitCode::ConnectionFailed,
+            JudgeResult::Cancelled { .. } => ExitCode::Cancelled,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CompileError {
+    /// of the compiler
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum CodetestResult {
+    Ran(CodetestOutput),
+    CompileError(CompileError),
+    NotSuchLang(String),
+}
+
+#[derive(Debug)]
+pub struct CodetestOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// in ms
+    pub time: u64,
+    /// whether the program was killed by the time limit
+    pub killed: bool,
+    pub exit_status: String,
+}
+
+/// How long to wait for the server to reply to a `cancel` request.
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A connection to the judge server.
+pub struct Client {
+    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// of the server
+    capabilities: Vec<Capability>,
+}
+
+impl Client {
+    /// Connects to the judge server, trying again `config.connect_retries` times
+    /// when it cannot be reached. `on_retry` is called with the number of the retry, from 1,
+    /// and the error before each retry.
+    ///
+    /// Then exchanges the protocol version and capabilities with the server,
+    /// failing with [`Error::ProtocolMismatch`] if they speak different versions.
+    /// A server that predates the handshake is taken as version 0 with no capabilities.
+    pub async fn connect(
+        config: &Config,
+        mut on_retry: impl FnMut(u32, &tungstenite::Error),
+    ) -> Result<Self, Error> {
+        let url = config.server_url();
+        let mut backoff = Duration::from_millis(500);
+        let mut retries = 0;
+        let ws_stream = loop {
+            let e = match timeout(config.connect_timeout, connect_async(url.as_str())).await {
+                Ok(Ok((ws_stream, _))) => break ws_stream,
+                // Only IO errors may go away by waiting.
+                Ok(Err(e @ tungstenite::Error::Io(_))) => e,
+                Ok(Err(e)) => return Err(Error::Connect(Box::new(e))),
+                Err(_) => tungstenite::Error::Io(io::ErrorKind::TimedOut.into()),
+            };
+            if retries == config.connect_retries {
+                return Err(Error::Connect(Box::new(e)));
+            }
+            retries += 1;
+            on_retry(retries, &e);
+            sleep(backoff).await;
+            backoff *= 2;
+        };
+        let mut client = Client {
+            ws_stream,
+            capabilities: Vec::new(),
+        };
+        client.handshake(config.hello_timeout).await?;
+        Ok(client)
+    }
+
+    /// Waits `hello_timeout` for the reply. A reply that comes later is checked by
+    /// [`check_late_hello`] when it turns up.
+    async fn handshake(&mut self, hello_timeout: Duration) -> Result<(), Error> {
+        self.send(json!({
+            "type": "hello",
+            "version": PROTOCOL_VERSION,
+            "capabilities": CAPABILITIES,
+        }))
+        .await?;
+        match timeout(hello_timeout, self.receive()).await {
+            Ok(Ok(ReternMessage::Hello {
+                version,
+                capabilities,
+            })) if version == PROTOCOL_VERSION => {
+                self.capabilities = capabilities;
+                Ok(())
+            }
+            Ok(Ok(ReternMessage::Hello { version, .. })) => Err(Error::ProtocolMismatch(version)),
+            // A server from before the handshake ignores it or answers in its old way.
+            Ok(Ok(_)) | Err(_) => Ok(()),
+            Ok(Err(e)) => Err(e),
+        }
+    }
+
+    /// Whether the server declared `capability` in the handshake.
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Adds `code` to `request`: as text in `code`, which every server reads, or as base64
+    /// in `code_base64` when the server declared `binary_sources`.
+    /// Fails if `code` is not UTF-8 and the server cannot take that.
+    fn with_source(
+        &self,
+        mut request: serde_json::Value,
+        code: &[u8],
+    ) -> Result<serde_json::Value, Error> {
+        if self.supports(Capability::BinarySources) {
+            request["code_base64"] = base64::encode(code).into();
+        } else {
+            let code = std::str::from_utf8(code).map_err(|_| Error::BinarySourceUnsupported)?;
+            request["code"] = code.into();
+        }
+        Ok(request)
+    }
+
+    /// Judges `code` on every test case of the problem.
+    ///
+    /// When `cancel` completes before the judge finishes, asks the server to stop the job
+    /// and finishes with [`JudgeResult::Cancelled`].
+    /// The stream ends early with an error if the server sends something
+    /// this client does not understand.
+    pub fn submit<'a>(
+        &'a mut self,
+        lang: &str,
+        problem_name: &str,
+        code: &[u8],
+        cancel: impl Future<Output = ()> + Send + 'a,
+    ) -> impl Stream<Item = Result<JudgeEvent, Error>> + 'a {
+        self.judge("submission", lang, problem_name, code, cancel)
+    }
+
+    /// Judges `code` on the public sample cases of the problem only, like [`Client::submit`].
+    pub fn run_samples<'a>(
+        &'a mut self,
+        lang: &str,
+        problem_name: &str,
+        code: &[u8],
+        cancel: impl Future<Output = ()> + Send + 'a,
+    ) -> impl Stream<Item = Result<JudgeEvent, Error>> + 'a {
+        self.judge("sample_run", lang, problem_name, code, cancel)
+    }
+
+    /// Asks the server to stop the job of this connection, as `cancel` of [`Client::submit`]
+    /// does. Returns whether the server confirmed it.
+    pub async fn cancel(&mut self) -> bool {
+        let can_cancel = self.supports(Capability::Cancellation);
+        cancel_job(&mut self.ws_stream, can_cancel).await
+    }
+
+    fn judge<'a>(
+        &'a mut self,
+        request_type: &str,
+        lang: &str,
+        problem_name: &str,
+        code: &[u8],
+        cancel: impl Future<Output = ()> + Send + 'a,
+    ) -> impl Stream<Item = Result<JudgeEvent, Error>> + 'a {
+        let request = self.with_source(
+            json!({
+                "type": request_type,
+                "lang": lang,
+                "problem_name": problem_name,
+            }),
+            code,
+        );
+        let can_cancel = self.supports(Capability::Cancellation);
+        let judge = Judge {
+            ws_stream: &mut self.ws_stream,
+            request: Some(request),
+            cancel: Box::pin(cancel),
+            can_cancel,
+            test_cases: Vec::new(),
+            finished: false,
+        };
+        stream::unfold(judge, |mut judge| async move {
+            if judge.finished {
+                return None;
+            }
+            let event = judge.next_event().await;
+            judge.finished = !matches!(
+                event,
+                Ok(JudgeEvent::TestCaseNames(_) | JudgeEvent::TestCaseResult { .. })
+            );
+            Some((event, judge))
+        })
+    }
+
+    /// Runs `code` once with `input` as stdin. The connection stays open for the next run.
+    pub async fn codetest(
+        &mut self,
+        lang: &str,
+        code: &[u8],
+        input: Option<&[u8]>,
+    ) -> Result<CodetestResult, Error> {
+        let request = self.with_source(
+            json!({
+                "type": "codetest",
+                "lang": lang,
+                "input" : input.map(base64::encode),
+            }),
+            code,
+        )?;
+        match self.request(request).await? {
+            ReternMessage::CodetestResult {
+                stdout,
+                stderr,
+                time,
+                killed,
+                status,
+            } => Ok(CodetestResult::Ran(CodetestOutput {
+                stdout: base64::decode(stdout)?,
+                stderr: base64::decode(stderr)?,
+                time,
+                killed,
+                exit_status: status,
+            })),
+            ReternMessage::CompileError {
+                code,
+                stdout,
+                stderr,
+            } => Ok(CodetestResult::CompileError(compile_error(
+                code, &stdout, &stderr,
+            )?)),
+            ReternMessage::NotSuchLang { lang } => Ok(CodetestResult::NotSuchLang(lang)),
+            r => Err(unexpected(r)),
+        }
+    }
+
+    pub async fn list_problems(&mut self) -> Result<Vec<Problem>, Error> {
+        match self.request(json!({ "type": "list_problems" })).await? {
+            ReternMessage::Problems { problems } => Ok(problems),
+            r => Err(unexpected(r)),
+        }
+    }
+
+    pub async fn list_langs(&mut self) -> Result<Vec<Lang>, Error> {
+        match self.request(json!({ "type": "list_langs" })).await? {
+            ReternMessage::Langs { langs } => Ok(langs),
+            r => Err(unexpected(r)),
+        }
+    }
+
+    /// Sends `request` and waits for a single reply.
+    async fn request(&mut self, request: serde_json::Value) -> Result<ReternMessage, Error> {
+        self.send(request).await?;
+        loop {
+            match self.receive().await? {
+                ReternMessage::Hello {
+                    version,
+                    capabilities,
+                } => {
+                    check_late_hello(version)?;
+                    // The request is already sent, but the next ones can use them.
+                    self.capabilities = capabilities;
+                }
+                message => return Ok(message),
+            }
+        }
+    }
+
+    async fn send(&mut self, message: serde_json::Value) -> Result<(), Error> {
+        self.ws_stream
+            .send(Message::Text(message.to_string()))
+            .await
+            .map_err(|_| Error::ServerClosed)
+    }
+
+    async fn receive(&mut self) -> Result<ReternMessage, Error> {
+        while let Some(Ok(message)) = self.ws_stream.next().await {
+            if let Message::Text(message) = message {
+                return Ok(serde_json::from_str(&message)?);
+            }
+        }
+        Err(Error::ServerClosed)
+    }
+}
+
+/// The state of a submission or sample run behind the stream of [`JudgeEvent`]s.
+struct Judge<'a> {
+    ws_stream: &'a mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// taken when sent
+    request: Option<Result<serde_json::Value, Error>>,
+    cancel: Pin<Box<dyn Future<Output = ()> + Send + 'a>>,
+    can_cancel: bool,
+    test_cases: Vec<(String, JudgeStatus)>,
+    finished: bool,
+}
+
+impl Judge<'_> {
+    async fn next_event(&mut self) -> Result<JudgeEvent, Error> {
+        if let Some(request) = self.request.take() {
+            let request = Message::Text(request?.to_string());
+            if self.ws_stream.send(request).await.is_err() {
+                return Ok(JudgeEvent::Finished(JudgeResult::Disconnected));
+            }
+        }
+        loop {
+            let message = tokio::select! {
+                message = self.ws_stream.next() => message,
+                () = &mut self.cancel => {
+                    let acknowledged = cancel_job(self.ws_stream, self.can_cancel).await;
+                    return Ok(JudgeEvent::Finished(JudgeResult::Cancelled { acknowledged }));
+                }
+            };
+            let message = match message {
+                Some(Ok(Message::Text(message))) => serde_json::from_str(&message)?,
+                // The server closes the connection when it has judged every test case.
+                Some(Ok(Message::Close(_))) => return Ok(JudgeEvent::Finished(self.result())),
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => return Ok(JudgeEvent::Finished(JudgeResult::Disconnected)),
+            };
+            return Ok(match message {
+                ReternMessage::TestCaseNames { ns } => {
+                    self.test_cases = ns.iter().map(|n| (n.clone(), JudgeStatus::Wj)).collect();
+                    JudgeEvent::TestCaseNames(ns)
+                }
+                ReternMessage::SubmissionResult {
+                    test_case_name,
+                    result,
+                    time,
+                    killed,
+                } => {
+                    let status = judge_status(&result, time, killed);
+                    match self
+                        .test_cases
+                        .iter_mut()
+                        .find(|(n, _)| *n == test_case_name)
+                    {
+                        Some(case) => case.1 = status,
+                        None => {
+                            return Err(Error::UnexpectedMessage(format!(
+                                "a result of unknown test case {test_case_name:?}"
+                            )))
+                        }
+                    }
+                    JudgeEvent::TestCaseResult {
+                        name: test_case_name,
+                        status,
+                    }
+                }
+                ReternMessage::CompileError {
+                    code,
+                    stdout,
+                    stderr,
+                } => JudgeEvent::Finished(JudgeResult::CompileError(compile_error(
+                    code, &stdout, &stderr,
+                )?)),
+                ReternMessage::NotSuchProblem { problem_name } => {
+                    JudgeEvent::Finished(JudgeResult::NotSuchProblem(problem_name))
+                }
+                ReternMessage::NotSuchLang { lang } => {
+                    JudgeEvent::Finished(JudgeResult::NotSuchLang(lang))
+                }
+                ReternMessage::Hello { version, .. } => {
+                    check_late_hello(version)?;
+                    continue;
+                }
+                r => return Err(unexpected(r)),
+            });
+        }
+    }
+
+    fn result(&self) -> JudgeResult {
+        if self.test_cases.is_empty() || self.test_cases.iter().any(|(_, s)| *s == JudgeStatus::Wj)
+        {
+            JudgeResult::Disconnected
+        } else {
+            let status = overall_result(self.test_cases.iter().map(|(_, s)| *s));
+            JudgeResult::Judged(status, self.test_cases.clone())
+        }
+    }
+}
+
+/// Checks the reply to the handshake that came after `hello_timeout`, when the server has
+/// already been taken for one that predates the handshake.
+fn check_late_hello(version: u32) -> Result<(), Error> {
+    if version == PROTOCOL_VERSION {
+        Ok(())
+    } else {
+        Err(Error::ProtocolMismatch(version))
+    }
+}
+
+/// Sends a `cancel` request and waits a little for the server to confirm it.
+async fn cancel_job(
+    ws_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+    can_cancel: bool,
+) -> bool {
+    if !can_cancel {
+        return false;
+    }
+    let cancel = Message::Text(json!({ "type": "cancel" }).to_string());
+    if ws_stream.send(cancel).await.is_err() {
+        return false;
+    }
+    timeout(CANCEL_TIMEOUT, async {
+        while let Some(Ok(message)) = ws_stream.next().await {
+            if let Message::Text(message) = message {
+                if let Ok(ReternMessage::Cancelled) = serde_json::from_str(&message) {
+                    return true;
+                }
+            }
+        }
+        false
+    })
+    .await
+    .unwrap_or(false)
+}
+
+fn compile_error(code: i32, stdout: &str, stderr: &str) -> Result<CompileError, Error> {
+    Ok(CompileError {
+        exit_code: code,
+        stdout: base64::decode(stdout)?,
+        stderr: base64::decode(stderr)?,
+    })
+}
+
+fn unexpected(message: ReternMessage) -> Error {
+    Error::UnexpectedMessage(format!("{message:?}"))
+}
+
+fn judge_status(result: &SubmissionResultType, time: u64, killed: bool) -> JudgeStatus {
+    if killed {
+        JudgeStatus::Tle(time)
+    } else {
+        match result {
+            SubmissionResultType::Ac => JudgeStatus::Ac(time),
+            SubmissionResultType::Re => JudgeStatus::Re(time),
+            SubmissionResultType::Wa => JudgeStatus::Wa(time),
+        }
+    }
+}
+
+/// The worst status of all test cases, with the longest time.
+fn overall_result(statuses: impl Iterator<Item = JudgeStatus>) -> JudgeStatus {
+    let mut ac = false;
+    let mut tle = false;
+    let mut wa = false;
+    let mut re = false;
+    let mut time = 0;
+    for s in statuses {
+        match s {
+            JudgeStatus::Ac(t) => {
+                ac = true;
+                time = time.max(t);
+            }
+            JudgeStatus::Tle(t) => {
+                tle = true;
+                time = time.max(t);
+            }
+            JudgeStatus::Wa(t) => {
+                wa = true;
+                time = time.max(t);
+            }
+            JudgeStatus::Re(t) => {
+                re = true;
+                time = time.max(t);
+            }
+            JudgeStatus::Wj => panic!(),
+        }
+    }
+    if tle {
+        JudgeStatus::Tle(time)
+    } else if re {
+        JudgeStatus::Re(time)
+    } else if wa {
+        JudgeStatus::Wa(time)
+    } else if ac {
+        JudgeStatus::Ac(time)
+    } else {
+        panic!()
+    }
+}
@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
+use termion::{color, style};
+
+/// When to use colors and bold text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorChoice {
+    /// when stdout is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("expected `auto`, `always` or `never`, found `{s}`")),
+        }
+    }
+}
+
+impl Display for ColorChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorChoice::Auto => write!(f, "auto"),
+            ColorChoice::Always => write!(f, "always"),
+            ColorChoice::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// A terminal color written as a name like `"green"` or `"light_red"`,
+/// or as a 256-color index like `"208"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ColorName(pub u8);
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl TryFrom<String> for ColorName {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if let Ok(n) = s.parse() {
+            return Ok(ColorName(n));
+        }
+        let (base, offset) = match s.strip_prefix("light_") {
+            Some(base) => (base, 8),
+            None => (&s[..], 0),
+        };
+        COLOR_NAMES
+            .iter()
+            .position(|&c| c == base)
+            .map(|i| ColorName(i as u8 + offset))
+            .ok_or_else(|| format!("unknown color `{s}`"))
+    }
+}
+
+/// Colors of the verdicts. Unset verdicts use the defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerdictColors {
+    pub ac: Option<ColorName>,
+    pub wa: Option<ColorName>,
+    pub re: Option<ColorName>,
+    pub tle: Option<ColorName>,
+}
+
+impl VerdictColors {
+    pub fn merge(&mut self, other: VerdictColors) {
+        self.ac = other.ac.or(self.ac);
+        self.wa = other.wa.or(self.wa);
+        self.re = other.re.or(self.re);
+        self.tle = other.tle.or(self.tle);
+    }
+}
+
+pub const RED: ColorName = ColorName(1);
+pub const GREEN: ColorName = ColorName(2);
+pub const YELLOW: ColorName = ColorName(3);
+pub const CYAN: ColorName = ColorName(6);
+
+/// Decorates text, or leaves it alone when colors are disabled.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub enabled: bool,
+    pub ac: ColorName,
+    pub wa: ColorName,
+    pub re: ColorName,
+    pub tle: ColorName,
+}
+
+impl Palette {
+    pub fn new(choice: ColorChoice, verdicts: &VerdictColors) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR")
+                    .filter(|v| !v.is_empty())
+                    .is_none()
+                    && atty::is(atty::Stream::Stdout)
+            }
+        };
+        Palette {
+            enabled,
+            ac: verdicts.ac.unwrap_or(GREEN),
+            wa: verdicts.wa.unwrap_or(YELLOW),
+            re: verdicts.re.unwrap_or(YELLOW),
+            tle: verdicts.tle.unwrap_or(YELLOW),
+        }
+    }
+
+    pub fn bold(&self, s: impl Display) -> String {
+        if self.enabled {
+            format!("{}{}{}", style::Bold, s, style::Reset)
+        } else {
+            s.to_string()
+        }
+    }
+
+    pub fn fg(&self, c: ColorName, s: impl Display) -> String {
+        if self.enabled {
+            format!(
+                "{}{}{}",
+                color::Fg(color::AnsiValue(c.0)),
+                s,
+                color::Fg(color::Reset)
+            )
+        } else {
+            s.to_string()
+        }
+    }
+
+    pub fn bg(&self, c: ColorName, s: impl Display) -> String {
+        if self.enabled {
+            format!(
+                "{}{}{}",
+                color::Bg(color::AnsiValue(c.0)),
+                s,
+                color::Bg(color::Reset)
+            )
+        } else {
+            s.to_string()
+        }
+    }
+}
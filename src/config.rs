@@ -0,0 +1,337 @@
+use crate::{
+    color::{ColorChoice, Palette, VerdictColors},
+    messages::{Locale, Msg},
+};
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+const SYSTEM_CONFIG: &str = "/etc/mmagolf/config.toml";
+
+const DEFAULT_EXTENSIONS: &[(&str, &str)] = &[
+    ("c", "c"),
+    ("cpp", "cpp"),
+    ("hs", "haskell"),
+    ("js", "javascript"),
+    ("pl", "perl"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("sh", "bash"),
+];
+const HOME_DIR: &str = env!("HOME");
+
+#[cfg(not(feature = "localhost_server"))]
+const DEFAULT_SERVER_ADDRESS: &str = "atlas";
+#[cfg(feature = "localhost_server")]
+const DEFAULT_SERVER_ADDRESS: &str = "localhost";
+
+#[cfg(debug_assertions)]
+const DEFAULT_BACK: &str = "target/debug/mmagolf-back";
+#[cfg(not(debug_assertions))]
+const DEFAULT_BACK: &str = "/home/mado/.cargo/bin/mmagolf-back";
+
+/// Settings shared by `mmagolf` and `mmagolf-back`.
+///
+/// Values are taken from `/etc/mmagolf/config.toml`, then
+/// `~/.config/mmagolf/config.toml`, then `MMAGOLF_*` environment variables,
+/// then command line flags. Later sources win.
+///
+/// `data_dir` and the judge servers come only from the system-wide file, since
+/// `mmagolf-back` records and publishes results under them with the owner's credentials.
+/// Users may still pick one of the `servers` listed there by name with `server`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server_address: String,
+    pub port: u16,
+    pub scheme: String,
+    pub back: PathBuf,
+    pub data_dir: PathBuf,
+    pub default_lang: Option<String>,
+    /// file extension (without the dot) to language
+    pub extensions: HashMap<String, String>,
+    pub show_policy: ShowPolicy,
+    /// locale of the Slack notifications, whatever the locale of the submitter
+    pub notification_locale: Locale,
+    pub contest_end: Option<DateTime<FixedOffset>>,
+    pub color: ColorChoice,
+    pub colors: VerdictColors,
+    pub locale: Locale,
+    /// how long to wait for each attempt to connect to the judge server
+    pub connect_timeout: Duration,
+    /// how many times to try connecting again, waiting twice as long each time
+    pub connect_retries: u32,
+    /// how long to wait for a judge to finish after connecting
+    pub judge_timeout: Duration,
+    /// how long to wait for the reply to the handshake before taking the server for one
+    /// that predates it, which every connection to such a server waits in full
+    pub hello_timeout: Duration,
+}
+
+/// A judge server that users may pick by name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Server {
+    pub server_address: String,
+    /// Falls back to the top-level `port`.
+    pub port: Option<u16>,
+    /// Falls back to the top-level `scheme`.
+    pub scheme: Option<String>,
+}
+
+/// Who may read the code of a stored submission.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShowPolicy {
+    /// only the author
+    Own,
+    /// only the author until `contest_end`, then everyone
+    #[default]
+    AfterContest,
+    /// everyone
+    All,
+}
+
+/// One layer of configuration. Unset fields fall through to the layer below.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialConfig {
+    /// Only honored in the system-wide file, like `port`, `scheme` and `data_dir`.
+    /// This is the judge server used when `server` is unset.
+    pub server_address: Option<String>,
+    pub port: Option<u16>,
+    pub scheme: Option<String>,
+    pub back: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    /// name of one of `servers`
+    pub server: Option<String>,
+    /// Only honored in the system-wide file.
+    #[serde(default)]
+    pub servers: HashMap<String, Server>,
+    pub default_lang: Option<String>,
+    /// Merged key by key with the lower layers.
+    #[serde(default)]
+    pub extensions: HashMap<String, String>,
+    /// Only honored in the system-wide file so that users cannot loosen it.
+    pub show_policy: Option<ShowPolicy>,
+    /// Only honored in the system-wide file. Defaults to `ja`.
+    pub notification_locale: Option<Locale>,
+    /// Only honored in the system-wide file. Written as an RFC 3339 string.
+    pub contest_end: Option<DateTime<FixedOffset>>,
+    pub color: Option<ColorChoice>,
+    /// Merged verdict by verdict with the lower layers.
+    #[serde(default)]
+    pub colors: VerdictColors,
+    /// Falls back to `LANG` when unset.
+    pub locale: Option<Locale>,
+    /// in seconds
+    pub connect_timeout: Option<u64>,
+    pub connect_retries: Option<u32>,
+    /// in seconds
+    pub judge_timeout: Option<u64>,
+    /// in milliseconds
+    pub hello_timeout: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Env(&'static str, String),
+    /// the name and the names in `servers`
+    UnknownServer(String, String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Env(name, value) => write!(f, "{}", Msg::InvalidEnv(name, value)),
+            ConfigError::UnknownServer(name, servers) => {
+                write!(f, "{}", Msg::UnknownServer(name, servers))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_with(PartialConfig::default())
+    }
+
+    /// Loads the config files and environment, then applies `overrides` on top.
+    pub fn load_with(overrides: PartialConfig) -> Result<Self, ConfigError> {
+        let mut c = PartialConfig {
+            extensions: DEFAULT_EXTENSIONS
+                .iter()
+                .map(|&(e, l)| (e.to_string(), l.to_string()))
+                .collect(),
+            ..PartialConfig::default()
+        };
+        c.merge(PartialConfig::from_file(Path::new(SYSTEM_CONFIG))?);
+        let show_policy = c.show_policy.take().unwrap_or_default();
+        let notification_locale = c.notification_locale.take().unwrap_or(Locale::Ja);
+        let contest_end = c.contest_end.take();
+        let server_address = c.server_address.take();
+        let port = c.port.take();
+        let scheme = c.scheme.take();
+        let data_dir = c.data_dir.take();
+        let servers = std::mem::take(&mut c.servers);
+        if let Some(path) = user_config_file() {
+            c.merge(PartialConfig::from_file(&path)?);
+        }
+        c.merge(PartialConfig::from_env()?);
+        c.merge(overrides);
+        let (server_address, port, scheme) = match &c.server {
+            None => (server_address, port, scheme),
+            Some(name) => {
+                let server = servers.get(name).ok_or_else(|| {
+                    let mut names: Vec<_> = servers.keys().map(String::as_str).collect();
+                    names.sort_unstable();
+                    ConfigError::UnknownServer(name.clone(), names.join(", "))
+                })?;
+                (
+                    Some(server.server_address.clone()),
+                    server.port.or(port),
+                    server.scheme.clone().or(scheme),
+                )
+            }
+        };
+        Ok(Config {
+            server_address: server_address.unwrap_or_else(|| DEFAULT_SERVER_ADDRESS.to_string()),
+            port: port.unwrap_or(5620),
+            scheme: scheme.unwrap_or_else(|| "ws".to_string()),
+            back: c.back.unwrap_or_else(|| PathBuf::from(DEFAULT_BACK)),
+            data_dir: data_dir.unwrap_or_else(|| Path::new(HOME_DIR).join(".local/share/mmagolf")),
+            default_lang: c.default_lang,
+            extensions: c.extensions,
+            show_policy,
+            notification_locale,
+            contest_end,
+            color: c.color.unwrap_or(ColorChoice::Auto),
+            colors: c.colors,
+            locale: c.locale.unwrap_or_else(Locale::from_env),
+            connect_timeout: Duration::from_secs(c.connect_timeout.unwrap_or(5)),
+            connect_retries: c.connect_retries.unwrap_or(3),
+            judge_timeout: Duration::from_secs(c.judge_timeout.unwrap_or(300)),
+            hello_timeout: Duration::from_millis(c.hello_timeout.unwrap_or(1000)),
+        })
+    }
+
+    pub fn palette(&self) -> Palette {
+        Palette::new(self.color, &self.colors)
+    }
+
+    pub fn contest_is_running(&self) -> bool {
+        self.contest_end.iter().all(|&end| chrono::Utc::now() < end)
+    }
+
+    pub fn server_url(&self) -> String {
+        format!("{}://{}:{}", self.scheme, self.server_address, self.port)
+    }
+}
+
+fn user_config_file() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")))
+        .map(|d| d.join("mmagolf/config.toml"))
+}
+
+const ENV_BACK: &str = "MMAGOLF_BACK";
+const ENV_SERVER: &str = "MMAGOLF_SERVER";
+const ENV_LANG: &str = "MMAGOLF_LANG";
+const ENV_COLOR: &str = "MMAGOLF_COLOR";
+const ENV_LOCALE: &str = "MMAGOLF_LOCALE";
+const ENV_CONNECT_TIMEOUT: &str = "MMAGOLF_CONNECT_TIMEOUT";
+const ENV_CONNECT_RETRIES: &str = "MMAGOLF_CONNECT_RETRIES";
+const ENV_JUDGE_TIMEOUT: &str = "MMAGOLF_JUDGE_TIMEOUT";
+const ENV_HELLO_TIMEOUT: &str = "MMAGOLF_HELLO_TIMEOUT";
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn parse_var<T: FromStr>(name: &'static str) -> Result<Option<T>, ConfigError> {
+    var(name)
+        .map(|v| v.parse().map_err(|_| ConfigError::Env(name, v)))
+        .transpose()
+}
+
+impl PartialConfig {
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(s) => toml::from_str(&s).map_err(|e| ConfigError::Parse(path.to_path_buf(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(PartialConfig::default()),
+            Err(e) => Err(ConfigError::Read(path.to_path_buf(), e)),
+        }
+    }
+
+    fn from_env() -> Result<Self, ConfigError> {
+        Ok(PartialConfig {
+            back: var(ENV_BACK).map(PathBuf::from),
+            server: var(ENV_SERVER),
+            default_lang: var(ENV_LANG),
+            color: parse_var(ENV_COLOR)?,
+            locale: parse_var(ENV_LOCALE)?,
+            connect_timeout: parse_var(ENV_CONNECT_TIMEOUT)?,
+            connect_retries: parse_var(ENV_CONNECT_RETRIES)?,
+            judge_timeout: parse_var(ENV_JUDGE_TIMEOUT)?,
+            hello_timeout: parse_var(ENV_HELLO_TIMEOUT)?,
+            ..PartialConfig::default()
+        })
+    }
+
+    fn merge(&mut self, other: PartialConfig) {
+        self.server_address = other.server_address.or(self.server_address.take());
+        self.port = other.port.or(self.port);
+        self.scheme = other.scheme.or(self.scheme.take());
+        self.back = other.back.or(self.back.take());
+        self.data_dir = other.data_dir.or(self.data_dir.take());
+        self.server = other.server.or(self.server.take());
+        self.servers.extend(other.servers);
+        self.default_lang = other.default_lang.or(self.default_lang.take());
+        self.extensions.extend(other.extensions);
+        self.show_policy = other.show_policy.or(self.show_policy);
+        self.notification_locale = other.notification_locale.or(self.notification_locale);
+        self.contest_end = other.contest_end.or(self.contest_end);
+        self.color = other.color.or(self.color);
+        self.colors.merge(other.colors);
+        self.locale = other.locale.or(self.locale);
+        self.connect_timeout = other.connect_timeout.or(self.connect_timeout);
+        self.connect_retries = other.connect_retries.or(self.connect_retries);
+        self.judge_timeout = other.judge_timeout.or(self.judge_timeout);
+        self.hello_timeout = other.hello_timeout.or(self.hello_timeout);
+    }
+
+    /// Environment variables that make a child process see the same overrides.
+    pub fn to_env(&self) -> Vec<(&'static str, OsString)> {
+        let mut vars = Vec::new();
+        if let Some(b) = &self.back {
+            vars.push((ENV_BACK, b.into()));
+        }
+        if let Some(s) = &self.server {
+            vars.push((ENV_SERVER, s.into()));
+        }
+        if let Some(l) = &self.default_lang {
+            vars.push((ENV_LANG, l.into()));
+        }
+        if let Some(c) = self.color {
+            vars.push((ENV_COLOR, c.to_string().into()));
+        }
+        vars
+    }
+}
@@ -0,0 +1,240 @@
+use crate::diff::units;
+
+#[derive(Debug)]
+pub struct Line {
+    /// 1-based
+    pub number: usize,
+    /// including the newline at the end, so that the bytes of all lines add up to the total
+    pub bytes: usize,
+    pub chars: usize,
+    /// bytes of spaces, tabs and carriage returns before the end of the line
+    pub trailing_whitespace: usize,
+    pub indent: Indent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    None,
+    Tabs,
+    Spaces,
+    Mixed,
+}
+
+#[derive(Debug)]
+pub struct Invisible<'a> {
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+    pub unit: &'a [u8],
+}
+
+#[derive(Debug)]
+pub struct Count<'a> {
+    pub lines: Vec<Line>,
+    /// each distinct non-ASCII character or invalid byte, with the number of occurrences,
+    /// in order of first appearance
+    pub non_ascii: Vec<(&'a [u8], usize)>,
+    pub tabs: usize,
+    pub spaces: usize,
+    pub invisible: Vec<Invisible<'a>>,
+}
+
+impl Count<'_> {
+    pub fn bytes(&self) -> usize {
+        self.lines.iter().map(|l| l.bytes).sum()
+    }
+
+    pub fn chars(&self) -> usize {
+        self.lines.iter().map(|l| l.chars).sum()
+    }
+
+    pub fn non_ascii_bytes(&self) -> usize {
+        self.non_ascii.iter().map(|(u, n)| u.len() * n).sum()
+    }
+
+    pub fn trailing_whitespace(&self) -> usize {
+        self.lines.iter().map(|l| l.trailing_whitespace).sum()
+    }
+}
+
+/// Breaks `code` down into what each byte is spent on.
+pub fn count(code: &[u8]) -> Count<'_> {
+    let mut count = Count {
+        lines: Vec::new(),
+        non_ascii: Vec::new(),
+        tabs: 0,
+        spaces: 0,
+        invisible: Vec::new(),
+    };
+    let mut rest = code;
+    while !rest.is_empty() {
+        let (line, r) = match rest.iter().position(|&b| b == b'\n') {
+            Some(i) => rest.split_at(i + 1),
+            None => (rest, &rest[rest.len()..]),
+        };
+        rest = r;
+        let number = count.lines.len() + 1;
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        let units = units(content);
+        for (column, &u) in units.iter().enumerate() {
+            match u {
+                b"\t" => count.tabs += 1,
+                b" " => count.spaces += 1,
+                _ => (),
+            }
+            if u.len() > 1 || !u[0].is_ascii() {
+                match count.non_ascii.iter_mut().find(|(v, _)| *v == u) {
+                    Some((_, n)) => *n += 1,
+                    None => count.non_ascii.push((u, 1)),
+                }
+            }
+            if is_invisible(u) {
+                count.invisible.push(Invisible {
+                    line: number,
+                    column: column + 1,
+                    unit: u,
+                });
+            }
+        }
+        let indent: Vec<u8> = content
+            .iter()
+            .copied()
+            .take_while(|&b| b == b' ' || b == b'\t')
+            .collect();
+        count.lines.push(Line {
+            number,
+            bytes: line.len(),
+            chars: units.len() + usize::from(line.len() != content.len()),
+            trailing_whitespace: content
+                .iter()
+                .rev()
+                .take_while(|&&b| matches!(b, b' ' | b'\t' | b'\r'))
+                .count(),
+            indent: match (indent.contains(&b'\t'), indent.contains(&b' ')) {
+                (false, false) => Indent::None,
+                (true, false) => Indent::Tabs,
+                (false, true) => Indent::Spaces,
+                (true, true) => Indent::Mixed,
+            },
+        });
+    }
+    count
+}
+
+/// Control characters other than newlines and tabs, and characters that look like
+/// nothing or like an ordinary space.
+pub fn is_invisible(unit: &[u8]) -> bool {
+    match std::str::from_utf8(unit) {
+        Ok("\t") => false,
+        Ok(s) => s.chars().all(|c| {
+            c.is_control()
+                || matches!(
+                    c,
+                    '\u{a0}'
+                        | '\u{ad}'
+                        | '\u{180e}'
+                        | '\u{2000}'..='\u{200f}'
+                        | '\u{2028}'..='\u{202f}'
+                        | '\u{205f}'..='\u{2064}'
+                        | '\u{3000}'
+                        | '\u{feff}'
+                )
+        }),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_add_up_to_the_code() {
+        let count = count(b"a\nbb\nccc");
+        assert_eq!(count.lines.len(), 3);
+        assert_eq!(
+            count.lines.iter().map(|l| l.bytes).collect::<Vec<_>>(),
+            [2, 3, 3]
+        );
+        assert_eq!(count.bytes(), 8);
+        assert_eq!(count.chars(), 8);
+    }
+
+    #[test]
+    fn empty_code_has_no_lines() {
+        assert!(count(b"").lines.is_empty());
+    }
+
+    #[test]
+    fn crlf_is_trailing_whitespace() {
+        let count = count(b"a \r\nb\r\n");
+        assert_eq!(count.lines.len(), 2);
+        assert_eq!(count.lines[0].bytes, 4);
+        assert_eq!(count.lines[0].trailing_whitespace, 2);
+        assert_eq!(count.lines[1].trailing_whitespace, 1);
+        assert_eq!(count.trailing_whitespace(), 3);
+        // Carriage returns cost a byte each, so they are pointed out.
+        assert_eq!(
+            count
+                .invisible
+                .iter()
+                .map(|i| (i.line, i.column))
+                .collect::<Vec<_>>(),
+            [(1, 3), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn tabs_and_indents() {
+        let count = count(b"\tx\n  y\n \tz\nw\t");
+        assert_eq!((count.tabs, count.spaces), (3, 3));
+        assert_eq!(
+            count.lines.iter().map(|l| l.indent).collect::<Vec<_>>(),
+            [Indent::Tabs, Indent::Spaces, Indent::Mixed, Indent::None]
+        );
+        assert_eq!(count.lines[3].trailing_whitespace, 1);
+        assert!(count.invisible.is_empty());
+    }
+
+    #[test]
+    fn invisible_characters_are_located_by_character() {
+        let code = "aあ\u{200b}b\u{3000}\nx\u{feff}";
+        let count = count(code.as_bytes());
+        assert_eq!(
+            count
+                .invisible
+                .iter()
+                .map(|i| (i.line, i.column, i.unit))
+                .collect::<Vec<_>>(),
+            [
+                (1, 3, "\u{200b}".as_bytes()),
+                (1, 5, "\u{3000}".as_bytes()),
+                (2, 2, "\u{feff}".as_bytes()),
+            ]
+        );
+        assert_eq!(count.lines[0].chars, 6);
+    }
+
+    #[test]
+    fn non_ascii_in_order_of_appearance() {
+        let count = count("ああい\u{ff}".as_bytes());
+        assert_eq!(
+            count.non_ascii,
+            [
+                ("あ".as_bytes(), 2),
+                ("い".as_bytes(), 1),
+                ("\u{ff}".as_bytes(), 1)
+            ]
+        );
+        assert_eq!(count.non_ascii_bytes(), 11);
+    }
+
+    #[test]
+    fn invalid_bytes_are_non_ascii_but_not_invisible() {
+        let count = count(b"a\xffb");
+        assert_eq!(count.non_ascii, [(&b"\xff"[..], 1)]);
+        assert!(count.invisible.is_empty());
+        assert_eq!(count.chars(), 3);
+    }
+}
@@ -0,0 +1,185 @@
+use similar::{capture_diff_slices, group_diff_ops, Algorithm, DiffTag};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Debug)]
+pub struct Hunk<'a> {
+    /// 1-based line number in the old code where the hunk starts
+    pub old_line: usize,
+    /// 1-based line number in the new code where the hunk starts
+    pub new_line: usize,
+    pub parts: Vec<(Change, &'a [u8])>,
+}
+
+impl Hunk<'_> {
+    pub fn deleted(&self) -> usize {
+        self.size_of(Change::Delete)
+    }
+
+    pub fn inserted(&self) -> usize {
+        self.size_of(Change::Insert)
+    }
+
+    fn size_of(&self, change: Change) -> usize {
+        self.parts
+            .iter()
+            .filter(|(c, _)| *c == change)
+            .map(|(_, s)| s.len())
+            .sum()
+    }
+}
+
+/// Splits `code` into UTF-8 characters.
+/// A byte that does not start a valid UTF-8 character becomes a unit of its own.
+pub fn units(code: &[u8]) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut rest = code;
+    while !rest.is_empty() {
+        let len = match rest[0] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        let len = if len <= rest.len() && std::str::from_utf8(&rest[..len]).is_ok() {
+            len
+        } else {
+            1
+        };
+        let (unit, r) = rest.split_at(len);
+        units.push(unit);
+        rest = r;
+    }
+    units
+}
+
+/// Diffs `old` and `new` character by character and groups the changes into
+/// hunks with `context` unchanged characters around them.
+pub fn diff<'a>(old: &'a [u8], new: &'a [u8], context: usize) -> Vec<Hunk<'a>> {
+    let old_units = units(old);
+    let new_units = units(new);
+    let old_offsets = offsets(&old_units);
+    let new_offsets = offsets(&new_units);
+    let ops = capture_diff_slices(Algorithm::Myers, &old_units, &new_units);
+    group_diff_ops(ops, context)
+        .into_iter()
+        .map(|group| {
+            let mut parts = Vec::new();
+            for op in &group {
+                let (tag, o, n) = op.as_tag_tuple();
+                let o = &old[old_offsets[o.start]..old_offsets[o.end]];
+                let n = &new[new_offsets[n.start]..new_offsets[n.end]];
+                match tag {
+                    DiffTag::Equal => parts.push((Change::Equal, o)),
+                    DiffTag::Delete => parts.push((Change::Delete, o)),
+                    DiffTag::Insert => parts.push((Change::Insert, n)),
+                    DiffTag::Replace => {
+                        parts.push((Change::Delete, o));
+                        parts.push((Change::Insert, n));
+                    }
+                }
+            }
+            let (_, o, n) = group[0].as_tag_tuple();
+            Hunk {
+                old_line: line_number(old, old_offsets[o.start]),
+                new_line: line_number(new, new_offsets[n.start]),
+                parts,
+            }
+        })
+        .collect()
+}
+
+fn offsets(units: &[&[u8]]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(units.len() + 1);
+    let mut o = 0;
+    offsets.push(o);
+    for u in units {
+        o += u.len();
+        offsets.push(o);
+    }
+    offsets
+}
+
+fn line_number(code: &[u8], offset: usize) -> usize {
+    code[..offset].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_are_characters() {
+        assert_eq!(
+            units("aあ😀é".as_bytes()),
+            ["a", "あ", "😀", "é"].map(str::as_bytes)
+        );
+    }
+
+    #[test]
+    fn invalid_bytes_are_units_of_their_own() {
+        assert_eq!(
+            units(b"\xe3\x81a\x80\xff"),
+            [&b"\xe3"[..], b"\x81", b"a", b"\x80", b"\xff"]
+        );
+        // a cut character at the end
+        assert_eq!(
+            units(b"a\xf0\x9f\x98"),
+            [&b"a"[..], b"\xf0", b"\x9f", b"\x98"]
+        );
+    }
+
+    #[test]
+    fn same_code_has_no_hunks() {
+        assert!(diff(b"puts 1", b"puts 1", 3).is_empty());
+    }
+
+    #[test]
+    fn hunk_counts_bytes_of_whole_characters() {
+        let hunks = diff("p 'あ'".as_bytes(), "p 'い'".as_bytes(), 0);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].deleted(), 3);
+        assert_eq!(hunks[0].inserted(), 3);
+        // Without context, the hunk may have empty unchanged parts around the change.
+        let changes: Vec<_> = hunks[0]
+            .parts
+            .iter()
+            .filter(|(_, s)| !s.is_empty())
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                &(Change::Delete, "あ".as_bytes()),
+                &(Change::Insert, "い".as_bytes())
+            ]
+        );
+    }
+
+    #[test]
+    fn hunks_keep_context_and_line_numbers() {
+        let hunks = diff(b"a\nb\nc\nd", b"a\nb\nc\nee", 2);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!((hunks[0].old_line, hunks[0].new_line), (3, 3));
+        assert_eq!(
+            hunks[0].parts,
+            [
+                (Change::Equal, &b"c\n"[..]),
+                (Change::Delete, b"d"),
+                (Change::Insert, b"ee"),
+            ]
+        );
+        assert_eq!((hunks[0].deleted(), hunks[0].inserted()), (1, 2));
+    }
+
+    #[test]
+    fn separate_changes_make_separate_hunks() {
+        let hunks = diff(b"a123456789b", b"A123456789B", 1);
+        assert_eq!(hunks.len(), 2);
+        assert!(hunks.iter().all(|h| h.deleted() == 1 && h.inserted() == 1));
+    }
+}
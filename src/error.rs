@@ -0,0 +1,105 @@
+use crate::{client::PROTOCOL_VERSION, messages::Msg, ExitCode};
+use std::{fmt::Display, io, path::PathBuf};
+use tokio_tungstenite::tungstenite;
+
+#[derive(Debug)]
+pub enum Error {
+    Connect(Box<tungstenite::Error>),
+    /// the judge server closed the connection without answering
+    ServerClosed,
+    InvalidJson(serde_json::Error),
+    InvalidBase64(base64::DecodeError),
+    /// the command `mmagolf` passes to `mmagolf-back` on stdin
+    InvalidCommand(serde_json::Error),
+    /// a message of the judge server that does not answer the request
+    UnexpectedMessage(String),
+    /// `judge_timeout` in seconds, which passed before the judge server answered
+    Timeout(u64),
+    /// the protocol version of the judge server
+    ProtocolMismatch(u32),
+    /// the code is not UTF-8 and the judge server does not declare `binary_sources`
+    BinarySourceUnsupported,
+    /// line number, from 1, and the line of the `submissions` file
+    InvalidSubmission(usize, String),
+    /// id of a submission whose code is not in `submitted_files`
+    MissingSubmittedFile(usize),
+    NotSuchSubmission(usize),
+    /// reading the code of another user while `show_policy` forbids it
+    PermissionDenied,
+    /// uid of the current user, which has no entry in the user database
+    UnknownUser(u32),
+    Io(PathBuf, io::Error),
+    /// writing to stdout or stderr
+    Output(io::Error),
+    /// copying the ranking to the web server
+    Ssh(io::Error),
+    Slack(slack_hook::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Connect(e) => match e.as_ref() {
+                tungstenite::Error::Io(e) => write!(f, "{}", Msg::ServerNotRunning(e)),
+                e => write!(f, "{}", Msg::ConnectionFailed(e)),
+            },
+            Error::ServerClosed => write!(f, "{}", Msg::ServerClosed),
+            Error::InvalidJson(e) => write!(f, "{}", Msg::InvalidMessage(e)),
+            Error::InvalidBase64(e) => write!(f, "{}", Msg::InvalidBase64(e)),
+            Error::InvalidCommand(e) => write!(f, "{}", Msg::InvalidCommand(e)),
+            Error::UnexpectedMessage(m) => write!(f, "{}", Msg::UnexpectedMessage(m)),
+            Error::Timeout(secs) => write!(f, "{}", Msg::JudgeTimeout(*secs)),
+            Error::ProtocolMismatch(v) if *v > PROTOCOL_VERSION => {
+                write!(f, "{}", Msg::UpgradeClient(*v, PROTOCOL_VERSION))
+            }
+            Error::ProtocolMismatch(v) => write!(f, "{}", Msg::UpgradeServer(*v, PROTOCOL_VERSION)),
+            Error::BinarySourceUnsupported => write!(f, "{}", Msg::BinarySourceUnsupported),
+            Error::InvalidSubmission(line, s) => write!(f, "{}", Msg::InvalidSubmission(*line, s)),
+            Error::MissingSubmittedFile(id) => write!(f, "{}", Msg::MissingSubmittedFile(*id)),
+            Error::NotSuchSubmission(id) => write!(f, "{}", Msg::NotSuchSubmission(*id)),
+            Error::PermissionDenied => write!(f, "{}", Msg::CannotSeeCode),
+            Error::UnknownUser(uid) => write!(f, "{}", Msg::UnknownUser(*uid)),
+            Error::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Error::Output(e) => write!(f, "{}", Msg::OutputFailed(e)),
+            Error::Ssh(e) => write!(f, "{}", Msg::RankingNotUploaded(e)),
+            Error::Slack(e) => write!(f, "{}", Msg::SlackNotNotified(e)),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::InvalidJson(e)
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(e: base64::DecodeError) -> Self {
+        Error::InvalidBase64(e)
+    }
+}
+
+impl From<ssh2::Error> for Error {
+    fn from(e: ssh2::Error) -> Self {
+        Error::Ssh(e.into())
+    }
+}
+
+impl From<slack_hook::Error> for Error {
+    fn from(e: slack_hook::Error) -> Self {
+        Error::Slack(e)
+    }
+}
+
+impl Error {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Connect(_) | Error::ServerClosed => ExitCode::ConnectionFailed,
+            Error::Timeout(_) => ExitCode::Timeout,
+            Error::ProtocolMismatch(_) => ExitCode::ProtocolMismatch,
+            _ => ExitCode::Failure,
+        }
+    }
+}
@@ -1,183 +1,421 @@
 use chrono::prelude::*;
-use futures_util::{SinkExt, StreamExt};
+use color::Palette;
+use diff::Change;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::fmt::Display;
-use tokio::{io::AsyncWriteExt, net::TcpStream, sync::mpsc::Sender};
-use tokio_tungstenite::{
-    connect_async,
-    tungstenite::{self, protocol::Message},
-    MaybeTlsStream, WebSocketStream,
-};
+use std::{borrow::Cow, collections::HashMap, fmt::Display, str::FromStr};
+
+pub mod client;
+pub mod color;
+pub mod config;
+pub mod count;
+pub mod diff;
+mod error;
+pub mod messages;
+
+pub use error::Error;
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Command {
     Submit {
+        /// base64 encoded
         code: String,
         lang: String,
         problem_name: String,
         dry_run: bool,
+        format: OutputFormat,
+        progress: Progress,
     },
     Codetest {
+        /// base64 encoded
         code: String,
         lang: String,
+        /// base64 encoded
         input: Option<String>,
+        /// When not empty, each case is run in turn instead of `input`.
+        cases: Vec<TestCase>,
+        /// When set, the sample cases of this problem are run instead of `input`.
+        problem_name: Option<String>,
+        format: OutputFormat,
+        /// only used with `problem_name`
+        progress: Progress,
     },
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all(deserialize = "snake_case"))]
-pub enum ReternMessage {
-    SubmissionResult {
-        test_case_name: String,
-        result: SubmissionResultType,
-        time: u64,
-        killed: bool,
-    },
-    CompileError {
-        code: i32,
-        stdout: String,
-        stderr: String,
+    ListProblems {
+        problem_name: Option<String>,
+        json: bool,
     },
-    CodetestResult {
-        stdout: String,
-        stderr: String,
-        time: u64,
-        killed: bool,
-        status: String,
+    ListLangs {
+        json: bool,
     },
-    TestCaseNames {
-        ns: Vec<String>,
+    Ranking {
+        problem_name: Option<String>,
+        top: usize,
+        by_lang: bool,
+        overall: bool,
     },
-    Close,
-    NotSuchProblem {
-        problem_name: String,
+    History {
+        /// `None` means the current user
+        user: Option<String>,
+        all_users: bool,
+        problem_name: Option<String>,
+        lang: Option<String>,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+        sort: SortKey,
+        json: bool,
     },
-    NotSuchLang {
-        lang: String,
+    Show {
+        id: usize,
+        hex: bool,
+        /// print only the code, without metadata
+        raw: bool,
     },
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq)]
-#[serde(rename_all(deserialize = "snake_case"))]
-pub enum SubmissionResultType {
-    Ac,
-    Re,
-    Wa,
-}
-
-pub async fn submit(
-    lang: &str,
-    probelem_name: &str,
-    code: &str,
-    mut ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
-    sender: Sender<ReternMessage>,
-) {
-    ws_stream
-        .send(Message::Text(
-            json!({
-                "type": "submission",
-                "lang": lang,
-                "problem_name": probelem_name,
-                "code": code,
-            })
-            .to_string(),
-        ))
-        .await
-        .unwrap();
-    ws_stream
-        .for_each(|message| async {
-            match message.unwrap() {
-                Message::Text(message) => {
-                    let data = serde_json::from_str(&message).unwrap();
-                    sender.send(data).await.unwrap();
-                }
-                Message::Close(_) => {
-                    let _ = sender.send(ReternMessage::Close).await;
-                }
-                _ => panic!(),
-            }
+/// An input for codetest and the stdout it should produce.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TestCase {
+    pub name: String,
+    /// base64 encoded
+    pub input: String,
+    /// base64 encoded
+    pub expected: Option<String>,
+}
+
+/// Exit codes of `mmagolf` and `mmagolf-back`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// AC, or any command other than submit and codetest that succeeded
+    Success = 0,
+    Failure = 1,
+    Wa = 10,
+    Re = 11,
+    Tle = 12,
+    CompileError = 13,
+    NotSuchProblem = 14,
+    NotSuchLang = 15,
+    ConnectionFailed = 16,
+    /// `submit --confirm` or `--only-if-improves` decided not to submit
+    NotSubmitted = 17,
+    /// the judge did not finish within `judge_timeout`
+    Timeout = 18,
+    /// the judge server speaks another version of the protocol
+    ProtocolMismatch = 19,
+    /// the judge was cancelled with Ctrl-C, as a shell reports a process killed by SIGINT
+    Cancelled = 130,
+}
+
+impl ExitCode {
+    pub fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+/// How `submit` shows the progress of judging in text output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Progress {
+    /// `Fancy` if stdout is a terminal, otherwise `Plain`
+    Auto,
+    /// redraw all test cases in place
+    Fancy,
+    /// print a line for each finished test case
+    Plain,
+    /// print only the result
+    None,
+}
+
+impl FromStr for Progress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Progress::Auto),
+            "fancy" => Ok(Progress::Fancy),
+            "plain" => Ok(Progress::Plain),
+            "none" => Ok(Progress::None),
+            _ => Err(format!(
+                "expected `auto`, `fancy`, `plain` or `none`, found `{s}`"
+            )),
+        }
+    }
+}
+
+impl Progress {
+    /// Replaces `Auto` with what it means for the current stdout.
+    pub fn resolve(self) -> Self {
+        match self {
+            Progress::Auto if atty::is(atty::Stream::Stdout) => Progress::Fancy,
+            Progress::Auto => Progress::Plain,
+            p => p,
+        }
+    }
+}
+
+/// How `submit` and `codetest` report results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// human readable text
+    Text,
+    /// a single JSON object at the end
+    Json,
+    /// one JSON object per line for each event, then the summary
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(format!("expected `text`, `json` or `jsonl`, found `{s}`")),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// The line to print for an intermediate event. Only `Jsonl` shows them.
+    pub fn event(self, event: &serde_json::Value) -> Option<String> {
+        (self == OutputFormat::Jsonl).then(|| event.to_string())
+    }
+
+    /// The text to print for the final result. `Text` has none.
+    pub fn summary(self, summary: &serde_json::Value) -> Option<String> {
+        match self {
+            OutputFormat::Text => None,
+            OutputFormat::Json => Some(format!("{summary:#}")),
+            OutputFormat::Jsonl => Some(summary.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Time,
+    Size,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "time" => Ok(SortKey::Time),
+            "size" => Ok(SortKey::Size),
+            _ => Err(format!("expected `time` or `size`, found `{s}`")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Problem {
+    pub name: String,
+    pub title: String,
+    pub statement: String,
+    pub test_case_names: Vec<String>,
+    /// in ms
+    pub time_limit: u64,
+    /// in MB
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Lang {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    /// `None` for interpreted languages
+    #[serde(default)]
+    pub compile: Option<String>,
+    pub run: String,
+}
+
+/// Makes invisible characters and invalid UTF-8 visible.
+/// Newlines are kept, and marked with `↵` if `mark_newlines` is set.
+pub fn escape(code: &[u8], mark_newlines: bool) -> String {
+    diff::units(code)
+        .into_iter()
+        .map(|u| match std::str::from_utf8(u) {
+            Ok("\n") if mark_newlines => "↵\n".to_string(),
+            Ok("\n") => "\n".to_string(),
+            Ok("\t") if mark_newlines => "→\t".to_string(),
+            Ok(c) if c.chars().all(|c| !c.is_control()) => c.to_string(),
+            Ok(c) => c.escape_default().to_string(),
+            Err(_) => format!("\\x{:02x}", u[0]),
         })
-        .await;
-}
-
-pub async fn codetest(
-    lang: String,
-    code: String,
-    input: Option<Vec<u8>>,
-    mut ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
-) {
-    ws_stream
-        .send(Message::Text(
-            json!({
-                "type": "codetest",
-                "lang": lang,
-                "code": code,
-                "input" : input.map(base64::encode),
-            })
-            .to_string(),
-        ))
-        .await
-        .unwrap();
-    ws_stream
-        .for_each(|message| async {
-            let message = message.unwrap();
-            if let Message::Text(message) = message {
-                let data: ReternMessage = serde_json::from_str(&message).unwrap();
-                match data {
-                    ReternMessage::CodetestResult {
-                        stdout,
-                        time,
-                        stderr,
-                        killed,
-                        status,
-                    } => {
-                        if killed {
-                            println!("TLEです。");
-                        }
-                        println!("time: {time} ms");
-                        println!("exit status: {}", status);
-                        tokio::io::stdout()
-                            .write_all(&base64::decode(stdout).unwrap())
-                            .await
-                            .unwrap();
-                        tokio::io::stderr()
-                            .write_all(&base64::decode(stderr).unwrap())
-                            .await
-                            .unwrap();
-                    }
-                    ReternMessage::NotSuchLang { lang } => {
-                        println!("Not such language: {lang}");
-                    }
-                    ReternMessage::CompileError {
-                        code,
-                        stdout,
-                        stderr,
-                    } => display_compile_error(code, stdout, stderr).await,
-                    _ => panic!("{:?}", data),
-                }
+        .collect()
+}
+
+/// `code` as is if it is valid UTF-8, otherwise escaped with [`escape`].
+pub fn printable(code: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(code) {
+        Ok(s) => Cow::Borrowed(s),
+        Err(_) => Cow::Owned(escape(code, false)),
+    }
+}
+
+/// The hunks of a character diff from `old` to `new`, one line per hunk after its header.
+pub fn format_diff(old: &[u8], new: &[u8], context: usize, palette: &Palette) -> String {
+    let mut out = String::new();
+    for hunk in diff::diff(old, new, context) {
+        let (deleted, inserted) = (hunk.deleted(), hunk.inserted());
+        let header = format!(
+            "@@ -{} +{} @@ -{} B +{} B ({:+} B)",
+            hunk.old_line,
+            hunk.new_line,
+            deleted,
+            inserted,
+            inserted as isize - deleted as isize,
+        );
+        out += &palette.fg(color::CYAN, header);
+        out.push('\n');
+        for (change, s) in hunk.parts {
+            out += &match change {
+                Change::Equal => escape(s, false),
+                // Without colors, mark changes the way `git diff --word-diff` does.
+                Change::Delete if !palette.enabled => format!("[-{}-]", escape(s, true)),
+                Change::Insert if !palette.enabled => format!("{{+{}+}}", escape(s, true)),
+                Change::Delete => palette.bg(color::RED, escape(s, true)),
+                Change::Insert => palette.bg(color::GREEN, escape(s, true)),
+            };
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Aligns the columns of `rows`. Columns listed in `right_aligned` are padded on the left.
+pub fn format_table(rows: &[Vec<String>], right_aligned: &[usize]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let w = cell.chars().count();
+            if i < widths.len() {
+                widths[i] = widths[i].max(w);
+            } else {
+                widths.push(w);
             }
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(i, (cell, &w))| {
+                    if right_aligned.contains(&i) {
+                        format!("{cell:>w$}")
+                    } else {
+                        format!("{cell:<w$}")
+                    }
+                })
+                .join("  ");
+            line.trim_end().to_string()
         })
-        .await;
+        .collect()
 }
 
-pub async fn connect_to_server(
-    server_address: &str,
-) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Error> {
-    let url = url::Url::parse(&format!("ws://{}:5620", server_address)).unwrap();
-    Ok(connect_async(url).await?.0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(unused)]
+pub enum JudgeStatus {
+    Ac(u64),
+    // Mle(u64),
+    Tle(u64),
+    Re(u64),
+    // Ole,
+    // Ie,
+    Wa(u64),
+    Wj,
+}
+
+impl JudgeStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JudgeStatus::Ac(_) => "AC",
+            JudgeStatus::Tle(_) => "TLE",
+            JudgeStatus::Re(_) => "RE",
+            JudgeStatus::Wa(_) => "WA",
+            JudgeStatus::Wj => "WJ",
+        }
+    }
+
+    pub fn time(&self) -> Option<u64> {
+        match *self {
+            JudgeStatus::Ac(t) | JudgeStatus::Tle(t) | JudgeStatus::Re(t) | JudgeStatus::Wa(t) => {
+                Some(t)
+            }
+            JudgeStatus::Wj => None,
+        }
+    }
+
+    /// The inverse of [`JudgeStatus::name`].
+    pub fn from_name(name: &str, time: u64) -> Option<Self> {
+        match name {
+            "AC" => Some(JudgeStatus::Ac(time)),
+            "TLE" => Some(JudgeStatus::Tle(time)),
+            "RE" => Some(JudgeStatus::Re(time)),
+            "WA" => Some(JudgeStatus::Wa(time)),
+            "WJ" => Some(JudgeStatus::Wj),
+            _ => None,
+        }
+    }
+
+    pub fn paint(&self, palette: &Palette) -> String {
+        let (c, t) = match *self {
+            JudgeStatus::Ac(t) => (palette.ac, t),
+            JudgeStatus::Tle(t) => (palette.tle, t),
+            JudgeStatus::Wa(t) => (palette.wa, t),
+            JudgeStatus::Re(t) => (palette.re, t),
+            JudgeStatus::Wj => return "...".to_string(),
+        };
+        format!("{}  {t: >7} ms", palette.bold(palette.fg(c, self.name())))
+    }
 }
 
-pub async fn display_compile_error(code: i32, stdout: String, stderr: String) {
-    let mut output = format!("Result: Compile Error\nexit code: {}\nstdout:\n", code).into_bytes();
-    output.append(&mut base64::decode(stdout).unwrap());
-    output.append(&mut "stderr:\n".as_bytes().to_vec());
-    output.append(&mut base64::decode(stderr).unwrap());
-    tokio::io::stdout().write_all(&output).await.unwrap();
+/// Shortest size for each pair of problem and language
+pub type LanguageShortests = HashMap<(String, String), usize>;
+
+/// Keeps the submissions that were the shortest in their problem and language
+/// when they were made, grouped by problem and sorted by size.
+pub fn rank_submissions(
+    submissions: Vec<Submission>,
+) -> (HashMap<String, Vec<Submission>>, LanguageShortests) {
+    let mut language_shortest: LanguageShortests = HashMap::new();
+    let mut submissions: Vec<_> = submissions
+        .into_iter()
+        .filter(|submission| {
+            let shortest = language_shortest
+                .get(&(submission.problem.to_string(), submission.lang.clone()))
+                .copied()
+                .unwrap_or(usize::MAX);
+            if submission.size < shortest {
+                language_shortest.insert(
+                    (submission.problem.to_string(), submission.lang.clone()),
+                    submission.size,
+                );
+                true
+            } else {
+                false
+            }
+        })
+        .collect();
+    submissions.sort_unstable_by_key(|s| (s.size, s.id));
+    let mut problems: HashMap<String, Vec<Submission>> = HashMap::new();
+    for s in submissions {
+        problems.entry(s.problem.clone()).or_default().push(s);
+    }
+    (problems, language_shortest)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Submission {
     pub id: usize,
     pub size: usize,
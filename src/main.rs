@@ -1,16 +1,40 @@
+use chrono::NaiveDate;
 use clap::{ArgGroup, Parser, Subcommand};
-use mmagolf::Command;
+use erase_output::Erase;
+use inotify::{Inotify, WatchMask};
+use itertools::Itertools;
+use mmagolf::{
+    color::{ColorChoice, Palette},
+    config::{Config, PartialConfig},
+    count::{self, Indent},
+    escape, format_diff, format_table,
+    messages::{self, Msg},
+    Command, ExitCode, OutputFormat, Problem, Progress, SortKey, Submission, TestCase,
+};
 use std::{
-    fs::read_to_string,
+    ffi::OsString,
     io::{Read, Write},
+    iter,
+    path::{Path, PathBuf},
     process::{self, exit, Stdio},
 };
 
+mod tui;
+
 #[derive(Debug, Parser)]
 #[clap(version, about, long_about = None)]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+    /// path to mmagolf-back
+    #[clap(long, global = true)]
+    back: Option<PathBuf>,
+    /// judge server to use, by its name in `servers` of /etc/mmagolf/config.toml
+    #[clap(long, global = true, value_name = "NAME")]
+    server: Option<String>,
+    /// when to use colors: auto, always or never (auto honors NO_COLOR)
+    #[clap(long, global = true, value_name = "WHEN")]
+    color: Option<ColorChoice>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -31,20 +55,33 @@ enum Commands {
         /// source code
         #[clap(short, long)]
         code: Option<String>,
-        /// language
+        /// language (default: inferred from the file extension, or `default_lang` in the config)
         #[clap(short, long)]
-        lang: String,
+        lang: Option<String>,
+        /// problem (default: inferred from the file name or its directory)
         #[clap(short, long)]
-        problem_name: String,
+        problem_name: Option<String>,
         #[clap(short, long)]
         dry_run: bool,
         #[clap(short, long)]
         retain_eof_newline: bool,
+        /// ask before submitting
+        #[clap(long)]
+        confirm: bool,
+        /// do not submit code that is not shorter than your best or the shortest in its language
+        #[clap(long)]
+        only_if_improves: bool,
+        /// `text`, `json` or `jsonl`
+        #[clap(long, default_value = "text")]
+        format: OutputFormat,
+        /// `auto`, `fancy`, `plain` or `none`
+        #[clap(long, default_value = "auto")]
+        progress: Progress,
     },
     /// run the code in the judge surver to see if the code works
     #[clap(
         arg_required_else_help = true,
-        override_usage = "echo <INPUT> | mmagolf codetest --lang <LANG> <--file <FILE>|--code <CODE>>",
+        override_usage = "echo <INPUT> | mmagolf codetest [--lang <LANG>] [--watch] <--file <FILE>|--code <CODE>>",
         group(
             ArgGroup::new("source")
                 .required(true)
@@ -58,85 +95,930 @@ enum Commands {
         /// source code
         #[clap(short, long)]
         code: Option<String>,
-        /// language
+        /// language (default: inferred from the file extension, or `default_lang` in the config)
+        #[clap(short, long)]
+        lang: Option<String>,
+        /// run again whenever the file is saved
+        #[clap(short, long, requires = "file")]
+        watch: bool,
+        /// read stdin from this file instead; repeat it to run several cases
+        #[clap(
+            short,
+            long,
+            value_name = "FILE",
+            multiple_occurrences = true,
+            conflicts_with = "watch"
+        )]
+        input: Vec<PathBuf>,
+        /// expected stdout for each --input, in the same order
+        ///
+        /// A newline at the end of either output is ignored when comparing.
+        #[clap(
+            short,
+            long,
+            value_name = "FILE",
+            multiple_occurrences = true,
+            requires = "input"
+        )]
+        expected: Vec<PathBuf>,
+        /// judge the code on the sample cases of this problem, without recording anything
+        #[clap(
+            short,
+            long = "problem",
+            value_name = "NAME",
+            conflicts_with_all = &["watch", "input"]
+        )]
+        problem_name: Option<String>,
+        /// `text`, `json` or `jsonl`
+        #[clap(long, default_value = "text", conflicts_with = "watch")]
+        format: OutputFormat,
+        /// `auto`, `fancy`, `plain` or `none`, for --problem
+        #[clap(long, default_value = "auto")]
+        progress: Progress,
+    },
+    /// list the problems of the contest
+    Problems {
+        /// show the full statement of this problem
+        problem_name: Option<String>,
+        /// print as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// list the languages available on the judge server
+    Langs {
+        /// print as JSON instead of a table
+        #[clap(long)]
+        json: bool,
+    },
+    /// show the ranking from the local submissions log
+    #[clap(group(ArgGroup::new("mode").args(&["problem-name", "overall"])))]
+    Ranking {
+        /// show only this problem
+        problem_name: Option<String>,
+        /// number of entries per problem
+        #[clap(short = 'n', long, default_value_t = 10)]
+        top: usize,
+        /// show only the best submission of each language
+        #[clap(short, long)]
+        by_lang: bool,
+        /// show the overall leaderboard of users
+        #[clap(short, long)]
+        overall: bool,
+    },
+    /// list accepted submissions
+    History {
+        /// show submissions of this user (default: you)
+        #[clap(short, long)]
+        user: Option<String>,
+        /// show submissions of all users
+        #[clap(short, long, conflicts_with = "user")]
+        all_users: bool,
+        #[clap(short, long)]
+        problem_name: Option<String>,
+        #[clap(short, long)]
+        lang: Option<String>,
+        /// show submissions on or after this date (YYYY-MM-DD)
+        #[clap(long)]
+        since: Option<NaiveDate>,
+        /// show submissions on or before this date (YYYY-MM-DD)
+        #[clap(long)]
+        until: Option<NaiveDate>,
+        /// `time` or `size`
+        #[clap(short, long, default_value = "time")]
+        sort: SortKey,
+        /// print as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// print the code of a submission
+    Show {
+        /// submission id
+        id: usize,
+        /// print a hex dump of the code
+        #[clap(short = 'x', long, conflicts_with = "output")]
+        hex: bool,
+        /// write the code to this file instead of printing it
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// compare two submissions or files character by character
+    ///
+    /// An argument that is a number and not an existing file is taken as a submission id.
+    Diff {
+        /// old submission id or file
+        old: String,
+        /// new submission id or file
+        new: String,
+        /// number of unchanged characters shown around each change
+        #[clap(short = 'U', long, default_value_t = 10)]
+        context: usize,
+        /// keep the newline at the end of files
+        #[clap(short, long)]
+        retain_eof_newline: bool,
+    },
+    /// show the size of code as `submit` would count it, with a breakdown
+    #[clap(
+        arg_required_else_help = true,
+        group(
+            ArgGroup::new("source")
+                .required(true)
+                .args(&["file", "code"]),
+        ),
+    )]
+    Count {
+        /// source file
+        #[clap(short, long)]
+        file: Option<String>,
+        /// source code
+        #[clap(short, long)]
+        code: Option<String>,
         #[clap(short, long)]
-        lang: String,
+        retain_eof_newline: bool,
     },
+    /// full-screen dashboard of the problems, the ranking and the running judge
+    ///
+    /// Source files in the current directory are matched to problems like `submit` infers
+    /// the problem, e.g. `fizzbuzz.hs` or `fizzbuzz/main.py`.
+    Tui,
 }
 
-#[cfg(debug_assertions)]
-const MMAGOLF_BACK: &str = "target/debug/mmagolf-back";
-#[cfg(not(debug_assertions))]
-const MMAGOLF_BACK: &str = "/home/mado/.cargo/bin/mmagolf-back";
-
 fn main() {
     let args = Cli::parse();
-    let command: Command = args.into();
-    let command = serde_json::to_string(&command).unwrap();
-    let mut back = process::Command::new(MMAGOLF_BACK)
+    let overrides = PartialConfig {
+        back: args.back,
+        server: args.server,
+        color: args.color,
+        ..PartialConfig::default()
+    };
+    let back_env = overrides.to_env();
+    let config = Config::load_with(overrides).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1)
+    });
+    messages::set_locale(config.locale);
+    let palette = config.palette();
+    match args.command {
+        Commands::Diff {
+            old,
+            new,
+            context,
+            retain_eof_newline,
+        } => {
+            let old_code = submission_or_file(&old, retain_eof_newline, &config, &back_env);
+            let new_code = submission_or_file(&new, retain_eof_newline, &config, &back_env);
+            display_diff((&old, &old_code), (&new, &new_code), context, &palette);
+        }
+        Commands::Count {
+            file,
+            code,
+            retain_eof_newline,
+        } => {
+            let raw = code_or_file(code, file, true);
+            let raw_len = raw.len();
+            let code = trim_eof_newline(raw, retain_eof_newline);
+            display_count(&code, code.len() != raw_len, &palette);
+        }
+        Commands::Tui => tui::run(&config, &back_env, palette),
+        Commands::Codetest {
+            file: Some(file),
+            lang,
+            watch: true,
+            ..
+        } => {
+            let lang = select_lang(lang, Some(&file), &config);
+            watch(&file, lang, stdin_input(), &config, &back_env, &palette);
+        }
+        command => {
+            let output = match &command {
+                Commands::Show { output, .. } => output.clone(),
+                _ => None,
+            };
+            let command = make_command(command, &config, &back_env);
+            if let Command::Submit { .. }
+            | Command::Codetest {
+                problem_name: Some(_),
+                ..
+            } = &command
+            {
+                // `mmagolf-back` cancels the judge on Ctrl-C.
+                // Keep waiting so that it can finish and report how it ended.
+                ctrlc::set_handler(|| ()).unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    exit(1)
+                });
+            }
+            let back_output = run_back(&command, output.is_some(), &config, &back_env);
+            if let Some(output) = output {
+                std::fs::write(&output, &back_output).unwrap_or_else(|e| {
+                    eprintln!("{}: {}", output.display(), e);
+                    exit(1)
+                });
+                println!("{}: {} B", output.display(), back_output.len());
+            }
+        }
+    }
+}
+
+/// Runs `mmagolf-back` with `command`. Returns its stdout if `capture_stdout` is set.
+/// Exits with the same code if `mmagolf-back` fails.
+fn run_back(
+    command: &Command,
+    capture_stdout: bool,
+    config: &Config,
+    back_env: &[(&str, OsString)],
+) -> Vec<u8> {
+    let stdout = if capture_stdout {
+        Stdio::piped()
+    } else {
+        Stdio::inherit()
+    };
+    let back_output = spawn_back(command, stdout, Stdio::inherit(), config, back_env);
+    if !back_output.status.success() {
+        exit(
+            back_output
+                .status
+                .code()
+                .unwrap_or(ExitCode::Failure as i32),
+        );
+    }
+    back_output.stdout
+}
+
+fn spawn_back(
+    command: &Command,
+    stdout: Stdio,
+    stderr: Stdio,
+    config: &Config,
+    back_env: &[(&str, OsString)],
+) -> process::Output {
+    start_back(command, stdout, stderr, config, back_env)
+        .wait_with_output()
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", config.back.display(), e);
+            exit(1)
+        })
+}
+
+/// Starts `mmagolf-back` and passes `command` to it without waiting for it to finish.
+fn start_back(
+    command: &Command,
+    stdout: Stdio,
+    stderr: Stdio,
+    config: &Config,
+    back_env: &[(&str, OsString)],
+) -> process::Child {
+    let command = serde_json::to_vec(command).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1)
+    });
+    let mut back = process::Command::new(&config.back)
+        .envs(back_env.iter().cloned())
         .stdin(Stdio::piped())
-        .stdout(Stdio::inherit())
+        .stdout(stdout)
+        .stderr(stderr)
         .spawn()
-        .unwrap();
-    back.stdin
-        .as_mut()
-        .unwrap()
-        .write_all(command.as_bytes())
-        .unwrap();
-    back.wait_with_output().unwrap();
-}
-
-impl From<Cli> for Command {
-    fn from(cli: Cli) -> Self {
-        match cli.command {
-            Commands::Submit {
-                file,
-                code,
-                lang,
-                problem_name,
-                dry_run,
-                retain_eof_newline,
-            } => Command::Submit {
-                code: code_or_file(code, file, retain_eof_newline),
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", config.back.display(), e);
+            exit(1)
+        });
+    // Dropping stdin closes it so that `mmagolf-back` sees the end of the command.
+    if let Some(mut stdin) = back.stdin.take() {
+        stdin.write_all(&command).unwrap_or_else(|e| {
+            eprintln!("{}: {}", config.back.display(), e);
+            exit(1)
+        });
+    }
+    back
+}
+
+/// Runs codetest on `file` every time it is written, redrawing the result in place.
+fn watch(
+    file: &str,
+    lang: String,
+    input: Option<String>,
+    config: &Config,
+    back_env: &[(&str, OsString)],
+    palette: &Palette,
+) {
+    // The output of `mmagolf-back` is captured, so decide on colors here.
+    let color = if palette.enabled {
+        ColorChoice::Always
+    } else {
+        ColorChoice::Never
+    };
+    let back_env: Vec<_> = back_env
+        .iter()
+        .cloned()
+        .chain(
+            PartialConfig {
+                color: Some(color),
+                ..PartialConfig::default()
+            }
+            .to_env(),
+        )
+        .collect();
+    let path = Path::new(file);
+    let dir = match path.parent() {
+        Some(d) if d != Path::new("") => d,
+        _ => Path::new("."),
+    };
+    let file_name = path.file_name().unwrap_or_else(|| {
+        eprintln!("{}", Msg::NotAFile(file));
+        exit(1)
+    });
+    let mut inotify = Inotify::init().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1)
+    });
+    // Watch the directory because many editors save by renaming a new file.
+    inotify
+        .watches()
+        .add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", dir.display(), e);
+            exit(1)
+        });
+    let mut buffer = [0; 4096];
+    let mut old = String::new();
+    loop {
+        // The file may be missing for a moment while an editor saves it.
+        let s = match std::fs::read(file) {
+            Ok(code) => {
+                let size = trim_eof_newline(code.clone(), false).len();
+                let command = Command::Codetest {
+                    code: base64::encode(code),
+                    lang: lang.clone(),
+                    input: input.clone(),
+                    cases: Vec::new(),
+                    problem_name: None,
+                    format: OutputFormat::Text,
+                    progress: Progress::None,
+                };
+                let output =
+                    spawn_back(&command, Stdio::piped(), Stdio::piped(), config, &back_env);
+                format!(
+                    "{}\n{}{}",
+                    palette.bold(format!("{file}: {size} B")),
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr),
+                )
+            }
+            Err(e) => format!("{}\n{}: {}\n", palette.bold(file), file, e),
+        };
+        print!("{}{}", Erase(&old), s);
+        std::io::stdout().flush().unwrap_or_default();
+        old = s;
+        loop {
+            let events = inotify
+                .read_events_blocking(&mut buffer)
+                .unwrap_or_else(|e| {
+                    eprintln!("{}: {}", dir.display(), e);
+                    exit(1)
+                });
+            if events.into_iter().any(|e| e.name == Some(file_name)) {
+                break;
+            }
+        }
+    }
+}
+
+fn submission_or_file(
+    arg: &str,
+    retain_eof_newline: bool,
+    config: &Config,
+    back_env: &[(&str, OsString)],
+) -> Vec<u8> {
+    match arg.parse() {
+        Ok(id) if !Path::new(arg).exists() => run_back(
+            &Command::Show {
+                id,
+                hex: false,
+                raw: true,
+            },
+            true,
+            config,
+            back_env,
+        ),
+        _ => code_or_file(None, Some(arg.to_string()), retain_eof_newline),
+    }
+}
+
+fn display_diff(old: (&str, &[u8]), new: (&str, &[u8]), context: usize, palette: &Palette) {
+    print!("{}", format_diff(old.1, new.1, context, palette));
+    println!(
+        "{}: {} B, {}: {} B ({:+} B)",
+        old.0,
+        old.1.len(),
+        new.0,
+        new.1.len(),
+        new.1.len() as isize - old.1.len() as isize,
+    );
+}
+
+/// `eof_newline_removed` tells that a newline at the end was trimmed from `code`.
+fn display_count(code: &[u8], eof_newline_removed: bool, palette: &Palette) {
+    let count = count::count(code);
+    let rows: Vec<_> = iter::once(
+        ["line", "bytes", "chars", "indent", "trailing whitespace"]
+            .map(String::from)
+            .to_vec(),
+    )
+    .chain(count.lines.iter().map(|l| {
+        vec![
+            l.number.to_string(),
+            l.bytes.to_string(),
+            l.chars.to_string(),
+            match l.indent {
+                Indent::None => "",
+                Indent::Tabs => "tabs",
+                Indent::Spaces => "spaces",
+                Indent::Mixed => "mixed",
+            }
+            .to_string(),
+            match l.trailing_whitespace {
+                0 => String::new(),
+                n => format!("{n} B"),
+            },
+        ]
+    }))
+    .collect();
+    print_table(&rows, &[0, 1, 2]);
+    println!();
+    println!(
+        "{}",
+        palette.bold(Msg::CountSummary(
+            count.bytes(),
+            count.chars(),
+            count.lines.len()
+        ))
+    );
+    if !count.non_ascii.is_empty() {
+        let chars: usize = count.non_ascii.iter().map(|(_, n)| n).sum();
+        println!("{}", Msg::CountNonAscii(chars, count.non_ascii_bytes()));
+        let rows: Vec<_> = count
+            .non_ascii
+            .iter()
+            .map(|(u, n)| {
+                let c = match std::str::from_utf8(u) {
+                    Ok(c) if count::is_invisible(u) => c.escape_default().to_string(),
+                    _ => escape(u, true),
+                };
+                vec![
+                    format!("  {c}"),
+                    code_point(u),
+                    format!("{} B", u.len()),
+                    format!("× {n}"),
+                ]
+            })
+            .collect();
+        print_table(&rows, &[2]);
+    }
+    println!("{}", Msg::CountWhitespace(count.tabs, count.spaces));
+    println!(
+        "{}",
+        Msg::CountTrailingWhitespace(count.trailing_whitespace())
+    );
+    if !count.invisible.is_empty() {
+        println!("{}", Msg::CountInvisible);
+        for i in &count.invisible {
+            println!("  {}:{}  {}", i.line, i.column, code_point(i.unit));
+        }
+    }
+    if eof_newline_removed {
+        println!("{}", Msg::EofNewlineRemoved);
+    }
+}
+
+fn print_table(rows: &[Vec<String>], right_aligned: &[usize]) {
+    for line in format_table(rows, right_aligned) {
+        println!("{line}");
+    }
+}
+
+/// `U+XXXX` for a character, or the byte for invalid UTF-8.
+fn code_point(unit: &[u8]) -> String {
+    match std::str::from_utf8(unit) {
+        Ok(s) => s.chars().map(|c| format!("U+{:04X}", c as u32)).join(" "),
+        Err(_) => format!("invalid UTF-8 \\x{:02x}", unit[0]),
+    }
+}
+
+fn make_command(command: Commands, config: &Config, back_env: &[(&str, OsString)]) -> Command {
+    match command {
+        Commands::Submit {
+            file,
+            code,
+            lang,
+            problem_name,
+            dry_run,
+            retain_eof_newline,
+            confirm,
+            only_if_improves,
+            format,
+            progress,
+        } => {
+            let lang = select_lang(lang, file.as_deref(), config);
+            let problem_name = problem_name.unwrap_or_else(|| match &file {
+                Some(file) => infer_problem(file, config, back_env),
+                None => {
+                    eprintln!("{}", Msg::NoProblemGiven);
+                    exit(1)
+                }
+            });
+            let code = code_or_file(code, file, retain_eof_newline);
+            let improves = compare_with_best(&problem_name, &lang, code.len(), config, back_env);
+            if only_if_improves && !improves {
+                eprintln!("{}", Msg::NoImprovement(&lang));
+                ExitCode::NotSubmitted.exit();
+            }
+            if confirm && !ask(&Msg::ConfirmSubmit) {
+                eprintln!("{}", Msg::NotSubmitted);
+                ExitCode::NotSubmitted.exit();
+            }
+            Command::Submit {
                 lang,
                 problem_name,
+                code: base64::encode(code),
                 dry_run,
+                format,
+                progress,
+            }
+        }
+        Commands::Codetest {
+            file,
+            code,
+            lang,
+            input,
+            expected,
+            problem_name,
+            format,
+            progress,
+            ..
+        } => Command::Codetest {
+            lang: select_lang(lang, file.as_deref(), config),
+            code: base64::encode(code_or_file(code, file, true)),
+            input: if input.is_empty() && problem_name.is_none() {
+                stdin_input()
+            } else {
+                None
             },
-            Commands::Codetest { file, code, lang } => Command::Codetest {
-                code: code_or_file(code, file, true),
-                lang,
-                input: if atty::is(atty::Stream::Stdin) {
-                    None
-                } else {
-                    Some({
-                        let mut input = Vec::new();
-                        std::io::stdin()
-                            .read_to_end(&mut input)
-                            .unwrap_or_else(|e| {
-                                eprintln!("{e}");
-                                exit(1)
-                            });
-                        base64::encode(input)
-                    })
-                },
-            },
+            cases: test_cases(&input, &expected),
+            problem_name,
+            format,
+            progress,
+        },
+        Commands::Problems { problem_name, json } => Command::ListProblems { problem_name, json },
+        Commands::Langs { json } => Command::ListLangs { json },
+        Commands::Ranking {
+            problem_name,
+            top,
+            by_lang,
+            overall,
+        } => Command::Ranking {
+            problem_name,
+            top,
+            by_lang,
+            overall,
+        },
+        Commands::History {
+            user,
+            all_users,
+            problem_name,
+            lang,
+            since,
+            until,
+            sort,
+            json,
+        } => Command::History {
+            user,
+            all_users,
+            problem_name,
+            lang,
+            since,
+            until,
+            sort,
+            json,
+        },
+        Commands::Show { id, hex, output } => Command::Show {
+            id,
+            hex,
+            raw: output.is_some(),
+        },
+        Commands::Diff { .. } | Commands::Count { .. } | Commands::Tui => unreachable!(),
+    }
+}
+
+/// Prints how `size` compares with your best and the shortests of the problem.
+/// Returns whether it beats your best or the shortest in `lang`.
+fn compare_with_best(
+    problem_name: &str,
+    lang: &str,
+    size: usize,
+    config: &Config,
+    back_env: &[(&str, OsString)],
+) -> bool {
+    let submissions = run_back(
+        &Command::History {
+            user: None,
+            all_users: true,
+            problem_name: Some(problem_name.to_string()),
+            lang: None,
+            since: None,
+            until: None,
+            sort: SortKey::Size,
+            json: true,
+        },
+        true,
+        config,
+        back_env,
+    );
+    let submissions: Vec<Submission> = serde_json::from_slice(&submissions).unwrap_or_else(|e| {
+        eprintln!("{}", Msg::InvalidBackOutput(&e));
+        exit(1)
+    });
+    let user = current_user();
+    let best = submissions.iter().find(|s| s.user == user).map(|s| s.size);
+    let shortest = submissions.first();
+    let language_shortest = submissions.iter().find(|s| s.lang == lang).map(|s| s.size);
+    let delta = |other: usize| size as isize - other as isize;
+    let mut deltas = Vec::new();
+    if let Some(best) = best {
+        deltas.push(Msg::VsBest(delta(best)).to_string());
+    }
+    if let Some(language_shortest) = language_shortest {
+        deltas.push(Msg::VsLanguageShortest(delta(language_shortest), lang).to_string());
+    }
+    if let Some(shortest) = shortest.filter(|s| s.lang != lang) {
+        deltas.push(Msg::VsShortest(delta(shortest.size)).to_string());
+    }
+    if !deltas.is_empty() {
+        eprintln!("{} B: {}", size, deltas.join(", "));
+    }
+    let beaten = |b: Option<usize>| b.is_some_and(|b| size >= b);
+    !(beaten(best) && beaten(language_shortest))
+}
+
+fn current_user() -> String {
+    users::get_current_username()
+        .map(|u| u.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Asks a yes/no question on the terminal. Anything but `y` is no.
+fn ask(question: &Msg) -> bool {
+    eprint!("{question} [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).unwrap_or_default();
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Pairs each input file with its expected output file, if any.
+fn test_cases(inputs: &[PathBuf], expected: &[PathBuf]) -> Vec<TestCase> {
+    if !expected.is_empty() && expected.len() != inputs.len() {
+        eprintln!(
+            "{}",
+            Msg::ExpectedCountMismatch(inputs.len(), expected.len())
+        );
+        exit(1)
+    }
+    let read = |path: &PathBuf| {
+        std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            exit(1)
+        })
+    };
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| TestCase {
+            name: input.display().to_string(),
+            input: base64::encode(read(input)),
+            expected: expected.get(i).map(|e| base64::encode(read(e))),
+        })
+        .collect()
+}
+
+/// Base64 encoded stdin, or `None` if stdin is a terminal.
+fn stdin_input() -> Option<String> {
+    if atty::is(atty::Stream::Stdin) {
+        None
+    } else {
+        let mut input = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut input)
+            .unwrap_or_else(|e| {
+                eprintln!("{e}");
+                exit(1)
+            });
+        Some(base64::encode(input))
+    }
+}
+
+/// `lang` if given, otherwise the language for the extension of `file`,
+/// otherwise `default_lang`.
+fn select_lang(lang: Option<String>, file: Option<&str>, config: &Config) -> String {
+    if let Some(lang) = lang {
+        return lang;
+    }
+    if let Some(file) = file {
+        if let Some(ext) = Path::new(file).extension() {
+            let ext = ext.to_string_lossy();
+            match config.extensions.get(&*ext) {
+                Some(lang) => {
+                    eprintln!("{}", Msg::LangFromExtension(lang, &ext));
+                    return lang.clone();
+                }
+                None if config.default_lang.is_none() => {
+                    let known = config
+                        .extensions
+                        .iter()
+                        .sorted()
+                        .map(|(e, l)| format!(".{e} ({l})"))
+                        .join(", ");
+                    eprintln!("{}", Msg::UnknownExtension(&ext, &known));
+                    exit(1)
+                }
+                None => (),
+            }
         }
     }
+    config.default_lang.clone().unwrap_or_else(|| {
+        eprintln!("{}", Msg::NoLangGiven);
+        exit(1)
+    })
 }
 
-fn code_or_file(code: Option<String>, file: Option<String>, retain_eof_newline: bool) -> String {
-    let mut s = match (file, code) {
-        (None, Some(code)) => code,
-        (Some(file), None) => read_to_string(&file).unwrap_or_else(|e| {
+/// Picks the problem whose name is the file stem or the parent directory,
+/// e.g. `fizzbuzz.hs` or `fizzbuzz/main.py`.
+fn infer_problem(file: &str, config: &Config, back_env: &[(&str, OsString)]) -> String {
+    let candidates = problem_candidates(Path::new(file));
+    let problems = run_back(
+        &Command::ListProblems {
+            problem_name: None,
+            json: true,
+        },
+        true,
+        config,
+        back_env,
+    );
+    let problems: Vec<Problem> = serde_json::from_slice(&problems).unwrap_or_else(|e| {
+        eprintln!("{}", Msg::InvalidBackOutput(&e));
+        exit(1)
+    });
+    let matches: Vec<_> = candidates
+        .iter()
+        .filter(|c| problems.iter().any(|p| &&p.name == c))
+        .collect();
+    match matches[..] {
+        [problem] => {
+            eprintln!("{}", Msg::ProblemFromFile(problem, file));
+            problem.clone()
+        }
+        [] => {
+            let problems = problems.iter().map(|p| &p.name).join(", ");
+            eprintln!("{}", Msg::CannotInferProblem(file, &problems));
+            exit(1)
+        }
+        _ => {
+            eprintln!(
+                "{}",
+                Msg::AmbiguousProblem(file, &matches.iter().join(", "))
+            );
+            exit(1)
+        }
+    }
+}
+
+/// Names of the problems `path` may be for: the file stem and the parent directory.
+fn problem_candidates(path: &Path) -> Vec<String> {
+    let absolute = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    [
+        path.file_stem(),
+        absolute.parent().and_then(Path::file_name),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|n| n.to_string_lossy().to_string())
+    .unique()
+    .collect()
+}
+
+fn code_or_file(code: Option<String>, file: Option<String>, retain_eof_newline: bool) -> Vec<u8> {
+    let code = match (file, code) {
+        (None, Some(code)) => code.into_bytes(),
+        (Some(file), None) => std::fs::read(&file).unwrap_or_else(|e| {
             eprintln!("{}: {}", file, e);
             exit(1)
         }),
-        _ => panic!(),
+        _ => {
+            eprintln!("{}", Msg::CodeOrFile);
+            exit(1)
+        }
     };
-    if !retain_eof_newline && s.ends_with('\n') {
-        s.pop();
-        s
-    } else {
-        s
+    trim_eof_newline(code, retain_eof_newline)
+}
+
+/// Drops one newline at the end of `code` unless `retain_eof_newline` is set.
+/// Every command counts code after this.
+fn trim_eof_newline(mut code: Vec<u8>, retain_eof_newline: bool) -> Vec<u8> {
+    if !retain_eof_newline && code.ends_with(b"\n") {
+        code.pop();
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mmagolf::{config::ShowPolicy, messages::Locale};
+    use std::time::Duration;
+
+    fn config(default_lang: Option<&str>) -> Config {
+        Config {
+            server_address: String::new(),
+            port: 0,
+            scheme: String::new(),
+            back: PathBuf::new(),
+            data_dir: PathBuf::new(),
+            default_lang: default_lang.map(String::from),
+            extensions: [("rb", "ruby"), ("py", "python")]
+                .iter()
+                .map(|&(e, l)| (e.to_string(), l.to_string()))
+                .collect(),
+            show_policy: ShowPolicy::default(),
+            notification_locale: Locale::Ja,
+            contest_end: None,
+            color: ColorChoice::Never,
+            colors: Default::default(),
+            locale: Locale::En,
+            connect_timeout: Duration::ZERO,
+            connect_retries: 0,
+            judge_timeout: Duration::ZERO,
+            hello_timeout: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn one_eof_newline_is_trimmed() {
+        assert_eq!(trim_eof_newline(b"a\n\n".to_vec(), false), b"a\n");
+        assert_eq!(trim_eof_newline(b"a\r\n".to_vec(), false), b"a\r");
+        assert_eq!(trim_eof_newline(b"a".to_vec(), false), b"a");
+        assert_eq!(trim_eof_newline(b"\n".to_vec(), false), b"");
+    }
+
+    #[test]
+    fn eof_newline_is_retained() {
+        assert_eq!(trim_eof_newline(b"a\n".to_vec(), true), b"a\n");
+    }
+
+    #[test]
+    fn candidates_are_stem_then_directory() {
+        assert_eq!(
+            problem_candidates(Path::new("/nonexistent/fizzbuzz/main.py")),
+            ["main", "fizzbuzz"]
+        );
+    }
+
+    #[test]
+    fn same_stem_and_directory_is_one_candidate() {
+        assert_eq!(
+            problem_candidates(Path::new("/nonexistent/fizzbuzz/fizzbuzz.rb")),
+            ["fizzbuzz"]
+        );
+    }
+
+    #[test]
+    fn file_without_extension_is_a_candidate() {
+        assert_eq!(
+            problem_candidates(Path::new("/nonexistent/golf/fizzbuzz")),
+            ["fizzbuzz", "golf"]
+        );
+    }
+
+    #[test]
+    fn given_lang_wins_over_extension() {
+        let lang = select_lang(Some("perl".to_string()), Some("a.rb"), &config(None));
+        assert_eq!(lang, "perl");
+    }
+
+    #[test]
+    fn lang_from_extension() {
+        assert_eq!(select_lang(None, Some("a.rb"), &config(None)), "ruby");
+    }
+
+    #[test]
+    fn extension_wins_over_default_lang() {
+        assert_eq!(
+            select_lang(None, Some("a.py"), &config(Some("ruby"))),
+            "python"
+        );
+    }
+
+    #[test]
+    fn default_lang_without_extension() {
+        let config = config(Some("ruby"));
+        assert_eq!(select_lang(None, Some("fizzbuzz"), &config), "ruby");
+        assert_eq!(select_lang(None, Some("a.unknown"), &config), "ruby");
+        assert_eq!(select_lang(None, None, &config), "ruby");
     }
 }
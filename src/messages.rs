@@ -0,0 +1,570 @@
+//! Catalog of the messages shown to users, in English and Japanese.
+//!
+//! Column headers and `key: value` labels of tables are left as they are,
+//! since they match the keys of the JSON output.
+
+use serde::Deserialize;
+use std::{env, fmt::Display, str::FromStr, sync::OnceLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Locale {
+    En,
+    Ja,
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Locale::En),
+            "ja" => Ok(Locale::Ja),
+            _ => Err(format!("expected `en` or `ja`, found `{s}`")),
+        }
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Locale::En => write!(f, "en"),
+            Locale::Ja => write!(f, "ja"),
+        }
+    }
+}
+
+impl Locale {
+    /// Reads `LC_ALL`, `LC_MESSAGES` and `LANG` in the order the C library does.
+    /// Anything other than Japanese falls back to English.
+    pub fn from_env() -> Self {
+        let lang = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|v| env::var(v).ok().filter(|l| !l.is_empty()));
+        match lang {
+            Some(l) if l.starts_with("ja") => Locale::Ja,
+            _ => Locale::En,
+        }
+    }
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Sets the locale of all messages. Call it once, right after loading the config.
+pub fn set_locale(locale: Locale) {
+    let _ = LOCALE.set(locale);
+}
+
+fn locale() -> Locale {
+    *LOCALE.get_or_init(Locale::from_env)
+}
+
+pub enum Msg<'a> {
+    NotSuchProblem(&'a str),
+    NotSuchLang(&'a str),
+    NotSuchSubmission(usize),
+    NoSubmissionsFor(&'a str),
+    /// verdict and code size
+    Result(&'a dyn Display, usize),
+    /// exit code of the compiler
+    CompileError(i32),
+    Stdout,
+    Stderr,
+    CodetestTle,
+    CodetestTime(u64),
+    CodetestExitStatus(&'a str),
+    Shortest,
+    LanguageShortest(&'a str),
+    /// mark in `mmagolf history`
+    HistoryShortest,
+    /// mark in `mmagolf history`
+    HistoryLanguageShortest(&'a str),
+    CannotSeeCode,
+    ServerNotRunning(&'a dyn Display),
+    ConnectionFailed(&'a dyn Display),
+    ServerClosed,
+    /// user, lang, problem, size and code
+    ShortestNotification(&'a str, &'a str, &'a str, usize, &'a str),
+    ShortestNotificationName,
+    NotAFile(&'a str),
+    NoProblemGiven,
+    NoLangGiven,
+    /// lang and extension
+    LangFromExtension(&'a str, &'a str),
+    /// extension and the known extensions
+    UnknownExtension(&'a str, &'a str),
+    /// problem and file
+    ProblemFromFile(&'a str, &'a str),
+    /// file and the problems
+    CannotInferProblem(&'a str, &'a str),
+    /// file and the candidates
+    AmbiguousProblem(&'a str, &'a str),
+    /// name and value of an environment variable
+    InvalidEnv(&'a str, &'a str),
+    EofNewlineRemoved,
+    /// number of inputs and expected outputs
+    ExpectedCountMismatch(usize, usize),
+    CasePassed,
+    CaseFailed,
+    /// passed and compared cases
+    CasesSummary(usize, usize),
+    TuiHelp,
+    /// problem
+    TuiNoFile(&'a str),
+    TuiJudgeRunning,
+    /// problem and file
+    TuiSubmitting(&'a str, &'a str),
+    /// problem and file
+    TuiRunningSamples(&'a str, &'a str),
+    /// difference in bytes
+    VsBest(isize),
+    /// difference in bytes
+    VsShortest(isize),
+    /// difference in bytes and lang
+    VsLanguageShortest(isize, &'a str),
+    /// lang
+    NoImprovement(&'a str),
+    ConfirmSubmit,
+    NotSubmitted,
+    /// error, retry and the number of retries
+    Retrying(&'a dyn Display, u32, u32),
+    /// in seconds
+    JudgeTimeout(u64),
+    JudgeInterrupted,
+    Cancelled,
+    CancelUnacknowledged,
+    InvalidMessage(&'a dyn Display),
+    /// the message as debug output
+    UnexpectedMessage(&'a str),
+    /// line number and the line
+    InvalidSubmission(usize, &'a str),
+    MissingSubmittedFile(usize),
+    RankingNotUploaded(&'a dyn Display),
+    SlackNotNotified(&'a dyn Display),
+    /// protocol versions of the server and the client
+    UpgradeClient(u32, u32),
+    /// protocol versions of the server and the client
+    UpgradeServer(u32, u32),
+    BinarySourceUnsupported,
+    InvalidBase64(&'a dyn Display),
+    InvalidCommand(&'a dyn Display),
+    InvalidBackOutput(&'a dyn Display),
+    /// uid
+    UnknownUser(u32),
+    OutputFailed(&'a dyn Display),
+    CodeOrFile,
+    /// bytes, characters and lines
+    CountSummary(usize, usize, usize),
+    /// characters and bytes
+    CountNonAscii(usize, usize),
+    /// tabs and spaces
+    CountWhitespace(usize, usize),
+    /// bytes
+    CountTrailingWhitespace(usize),
+    CountInvisible,
+    /// the name and the names of the servers
+    UnknownServer(&'a str, &'a str),
+}
+
+impl Display for Msg<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_in(locale(), f)
+    }
+}
+
+impl Msg<'_> {
+    /// The message in `locale`, whatever the locale of the user is.
+    pub fn to_string_in(&self, locale: Locale) -> String {
+        struct InLocale<'a, 'b>(&'a Msg<'b>, Locale);
+        impl Display for InLocale<'_, '_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.write_in(self.1, f)
+            }
+        }
+        InLocale(self, locale).to_string()
+    }
+
+    fn write_in(&self, locale: Locale, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match locale {
+            Locale::En => self.en(f),
+            Locale::Ja => self.ja(f),
+        }
+    }
+}
+
+impl Msg<'_> {
+    fn en(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Msg::NotSuchProblem(p) => write!(f, "Not such problem: {p}"),
+            Msg::NotSuchLang(l) => write!(f, "Not such language: {l}"),
+            Msg::NotSuchSubmission(id) => write!(f, "Not such submission: {id}"),
+            Msg::NoSubmissionsFor(p) => write!(f, "No submissions for {p}"),
+            Msg::Result(verdict, size) => write!(f, "Result: {verdict}, {size} B"),
+            Msg::CompileError(code) => write!(f, "Result: Compile Error\nexit code: {code}"),
+            Msg::Stdout => write!(f, "stdout:"),
+            Msg::Stderr => write!(f, "stderr:"),
+            Msg::CodetestTle => write!(f, "Time limit exceeded."),
+            Msg::CodetestTime(t) => write!(f, "time: {t} ms"),
+            Msg::CodetestExitStatus(s) => write!(f, "exit status: {s}"),
+            Msg::Shortest => write!(f, "Shortest! 🎉"),
+            Msg::LanguageShortest(l) => write!(f, "Shortest code in {l}! 🎉"),
+            Msg::HistoryShortest => write!(f, "shortest"),
+            Msg::HistoryLanguageShortest(l) => write!(f, "shortest in {l}"),
+            Msg::CannotSeeCode => write!(
+                f,
+                "You cannot see other users' code while the contest is running."
+            ),
+            Msg::ServerNotRunning(e) => write!(
+                f,
+                "Could not connect to the judge server. It may not be running. {e}"
+            ),
+            Msg::ConnectionFailed(e) => write!(
+                f,
+                "Could not connect to the judge server for an unknown reason: {e}"
+            ),
+            Msg::ServerClosed => write!(
+                f,
+                "The judge server closed the connection without answering."
+            ),
+            Msg::ShortestNotification(user, lang, problem, size, code) => write!(
+                f,
+                "{user} set a new shortest for {problem} in {lang}! ({size} B)\n```{code}```"
+            ),
+            Msg::ShortestNotificationName => write!(f, "Shortest updates"),
+            Msg::NotAFile(file) => write!(f, "{file}: not a file"),
+            Msg::NoProblemGiven => write!(f, "no problem given: use --problem-name"),
+            Msg::NoLangGiven => write!(
+                f,
+                "no language given: use --lang or set default_lang in the config"
+            ),
+            Msg::LangFromExtension(lang, ext) => write!(f, "lang: {lang} (from .{ext})"),
+            Msg::UnknownExtension(ext, known) => write!(
+                f,
+                "unknown extension .{ext}: use --lang or add it to [extensions] in the config\n\
+                known extensions: {known}"
+            ),
+            Msg::ProblemFromFile(problem, file) => write!(f, "problem: {problem} (from {file})"),
+            Msg::CannotInferProblem(file, problems) => write!(
+                f,
+                "could not infer the problem from {file}: use --problem-name\n\
+                problems: {problems}"
+            ),
+            Msg::AmbiguousProblem(file, candidates) => write!(
+                f,
+                "ambiguous problem for {file}: use --problem-name\n\
+                candidates: {candidates}"
+            ),
+            Msg::InvalidEnv(name, value) => write!(f, "{name}: invalid value {value:?}"),
+            Msg::EofNewlineRemoved => write!(
+                f,
+                "The newline at the end is not counted, as in submit. Use -r to keep it."
+            ),
+            Msg::ExpectedCountMismatch(inputs, expected) => write!(
+                f,
+                "{expected} --expected for {inputs} --input: give one for each input"
+            ),
+            Msg::CasePassed => write!(f, "passed"),
+            Msg::CaseFailed => write!(f, "failed"),
+            Msg::CasesSummary(passed, total) => write!(f, "{passed}/{total} passed"),
+            Msg::TuiHelp => write!(
+                f,
+                "j/k: select  f: next file  s: submit  t: sample cases  r: reload  q: quit"
+            ),
+            Msg::TuiNoFile(problem) => write!(
+                f,
+                "no file for {problem}: name it like {problem}.rb or {problem}/main.rb"
+            ),
+            Msg::TuiJudgeRunning => write!(f, "wait for the running judge to finish"),
+            Msg::TuiSubmitting(problem, file) => write!(f, "submit {file} to {problem}"),
+            Msg::TuiRunningSamples(problem, file) => {
+                write!(f, "sample cases of {problem} on {file}")
+            }
+            Msg::VsBest(d) => write!(f, "{d:+} B vs your best"),
+            Msg::VsShortest(d) => write!(f, "{d:+} B vs shortest"),
+            Msg::VsLanguageShortest(d, lang) => write!(f, "{d:+} B vs shortest in {lang}"),
+            Msg::NoImprovement(lang) => write!(
+                f,
+                "Not submitted: not shorter than your best or the shortest in {lang}."
+            ),
+            Msg::ConfirmSubmit => write!(f, "Submit?"),
+            Msg::NotSubmitted => write!(f, "Not submitted."),
+            Msg::Retrying(e, retry, retries) => write!(
+                f,
+                "Could not connect to the judge server: {e}. Retrying ({retry}/{retries})"
+            ),
+            Msg::JudgeTimeout(secs) => write!(
+                f,
+                "The judge did not finish in {secs} s. Gave up waiting (judge_timeout)."
+            ),
+            Msg::JudgeInterrupted => write!(
+                f,
+                "The judge server stopped before judging every test case."
+            ),
+            Msg::Cancelled => write!(f, "Cancelled."),
+            Msg::CancelUnacknowledged => write!(
+                f,
+                "Cancelled, but the judge server did not confirm it. \
+                The judge may still be running on the server."
+            ),
+            Msg::InvalidMessage(e) => write!(
+                f,
+                "The judge server sent a broken message: {e}\n\
+                If this keeps happening, please upgrade mmagolf."
+            ),
+            Msg::UnexpectedMessage(m) => write!(
+                f,
+                "The judge server sent an unexpected message: {m}\n\
+                If this keeps happening, please upgrade mmagolf."
+            ),
+            Msg::InvalidSubmission(line, s) => {
+                write!(f, "Line {line} of the submissions file is broken: {s}")
+            }
+            Msg::MissingSubmittedFile(id) => write!(
+                f,
+                "The code of submission {id} is missing from submitted_files."
+            ),
+            Msg::RankingNotUploaded(e) => {
+                write!(f, "Could not upload the ranking to the web server: {e}")
+            }
+            Msg::SlackNotNotified(e) => write!(f, "Could not post the new shortest to Slack: {e}"),
+            Msg::UpgradeClient(server, client) => write!(
+                f,
+                "The judge server speaks protocol version {server}, \
+                but this mmagolf only speaks version {client}. Please upgrade mmagolf."
+            ),
+            Msg::UpgradeServer(server, client) => write!(
+                f,
+                "The judge server speaks protocol version {server}, \
+                older than version {client} of this mmagolf. \
+                Please ask the administrator to upgrade it."
+            ),
+            Msg::BinarySourceUnsupported => write!(
+                f,
+                "The judge server does not accept code that is not UTF-8."
+            ),
+            Msg::InvalidBase64(e) => write!(
+                f,
+                "Could not decode base64 data: {e}\n\
+                mmagolf, mmagolf-back or the judge server may be out of date."
+            ),
+            Msg::InvalidCommand(e) => write!(
+                f,
+                "mmagolf-back could not read the command from mmagolf: {e}\n\
+                mmagolf and mmagolf-back may be of different versions."
+            ),
+            Msg::InvalidBackOutput(e) => write!(
+                f,
+                "Could not read the output of mmagolf-back: {e}\n\
+                mmagolf and mmagolf-back may be of different versions."
+            ),
+            Msg::UnknownUser(uid) => write!(f, "There is no user with uid {uid}."),
+            Msg::OutputFailed(e) => write!(f, "Could not write the output: {e}"),
+            Msg::CodeOrFile => write!(f, "Give either --file or --code."),
+            Msg::CountSummary(bytes, chars, lines) => {
+                write!(f, "size: {bytes} B, chars: {chars}, lines: {lines}")
+            }
+            Msg::CountNonAscii(chars, bytes) => write!(
+                f,
+                "non-ASCII: {chars} chars, {bytes} B ({:+} B)",
+                *bytes as isize - *chars as isize
+            ),
+            Msg::CountWhitespace(tabs, spaces) => write!(f, "tabs: {tabs}, spaces: {spaces}"),
+            Msg::CountTrailingWhitespace(bytes) => write!(f, "trailing whitespace: {bytes} B"),
+            Msg::CountInvisible => write!(f, "invisible characters:"),
+            Msg::UnknownServer(name, servers) => write!(
+                f,
+                "There is no judge server named {name:?}. \
+                The servers in /etc/mmagolf/config.toml are: {servers}"
+            ),
+        }
+    }
+
+    fn ja(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Msg::NotSuchProblem(p) => write!(f, "問題 {p} は存在しません。"),
+            Msg::NotSuchLang(l) => write!(f, "言語 {l} は存在しません。"),
+            Msg::NotSuchSubmission(id) => write!(f, "提出 {id} は存在しません。"),
+            Msg::NoSubmissionsFor(p) => write!(f, "{p} への提出はまだありません。"),
+            Msg::Result(verdict, size) => write!(f, "結果: {verdict}, {size} B"),
+            Msg::CompileError(code) => {
+                write!(f, "結果: コンパイルエラー\n終了コード: {code}")
+            }
+            Msg::Stdout => write!(f, "標準出力:"),
+            Msg::Stderr => write!(f, "標準エラー出力:"),
+            Msg::CodetestTle => write!(f, "TLEです。"),
+            Msg::CodetestTime(t) => write!(f, "実行時間: {t} ms"),
+            Msg::CodetestExitStatus(s) => write!(f, "終了ステータス: {s}"),
+            Msg::Shortest => write!(f, "Shortestです！🎉"),
+            Msg::LanguageShortest(l) => write!(f, "{l} でのShortestです！🎉"),
+            Msg::HistoryShortest => write!(f, "Shortest"),
+            Msg::HistoryLanguageShortest(l) => write!(f, "{l} でShortest"),
+            Msg::CannotSeeCode => write!(f, "コンテスト中は他の人のコードを見られません。"),
+            Msg::ServerNotRunning(e) => write!(
+                f,
+                "ジャッジサーバーに接続できませんでした。\
+                ジャッジサーバーが動いていないかもしれません。{e}"
+            ),
+            Msg::ConnectionFailed(e) => write!(
+                f,
+                "ジャッジサーバーに接続できませんでした。原因はよくわかりません。:{e}"
+            ),
+            Msg::ServerClosed => {
+                write!(f, "ジャッジサーバーが応答せずに接続を閉じました。")
+            }
+            Msg::ShortestNotification(user, lang, problem, size, code) => write!(
+                f,
+                "{user} が {lang} で {problem} のShortestを更新しました！（{size} B）\n```{code}```"
+            ),
+            Msg::ShortestNotificationName => write!(f, "Shortest更新通知"),
+            Msg::NotAFile(file) => write!(f, "{file} はファイルではありません。"),
+            Msg::NoProblemGiven => write!(f, "問題が指定されていません。--problem-name で指定してください。"),
+            Msg::NoLangGiven => write!(
+                f,
+                "言語が指定されていません。--lang で指定するか、設定ファイルに default_lang を書いてください。"
+            ),
+            Msg::LangFromExtension(lang, ext) => write!(f, "言語: {lang}（.{ext} から推測）"),
+            Msg::UnknownExtension(ext, known) => write!(
+                f,
+                "拡張子 .{ext} の言語がわかりません。--lang で指定するか、\
+                設定ファイルの [extensions] に追加してください。\n\
+                既知の拡張子: {known}"
+            ),
+            Msg::ProblemFromFile(problem, file) => {
+                write!(f, "問題: {problem}（{file} から推測）")
+            }
+            Msg::CannotInferProblem(file, problems) => write!(
+                f,
+                "{file} から問題を推測できませんでした。--problem-name で指定してください。\n\
+                問題: {problems}"
+            ),
+            Msg::AmbiguousProblem(file, candidates) => write!(
+                f,
+                "{file} に当てはまる問題が複数あります。--problem-name で指定してください。\n\
+                候補: {candidates}"
+            ),
+            Msg::InvalidEnv(name, value) => write!(f, "{name}: 値 {value:?} は不正です。"),
+            Msg::EofNewlineRemoved => write!(
+                f,
+                "submit と同じく、末尾の改行は数えていません。残すには -r を付けてください。"
+            ),
+            Msg::ExpectedCountMismatch(inputs, expected) => write!(
+                f,
+                "--input が {inputs} 個なのに --expected が {expected} 個です。入力ごとに 1 つずつ指定してください。"
+            ),
+            Msg::CasePassed => write!(f, "一致"),
+            Msg::CaseFailed => write!(f, "不一致"),
+            Msg::CasesSummary(passed, total) => write!(f, "{total} 件中 {passed} 件一致"),
+            Msg::TuiHelp => write!(
+                f,
+                "j/k: 選択  f: 次のファイル  s: 提出  t: サンプルで実行  r: 再読み込み  q: 終了"
+            ),
+            Msg::TuiNoFile(problem) => write!(
+                f,
+                "{problem} のファイルがありません。{problem}.rb や {problem}/main.rb のような名前にしてください。"
+            ),
+            Msg::TuiJudgeRunning => write!(f, "実行中のジャッジが終わるまで待ってください。"),
+            Msg::TuiSubmitting(problem, file) => write!(f, "{file} を {problem} に提出"),
+            Msg::TuiRunningSamples(problem, file) => {
+                write!(f, "{file} を {problem} のサンプルで実行")
+            }
+            Msg::VsBest(d) => write!(f, "自己ベスト比 {d:+} B"),
+            Msg::VsShortest(d) => write!(f, "Shortest比 {d:+} B"),
+            Msg::VsLanguageShortest(d, lang) => write!(f, "{lang} のShortest比 {d:+} B"),
+            Msg::NoImprovement(lang) => write!(
+                f,
+                "自己ベストも {lang} のShortestも更新できないため、提出しませんでした。"
+            ),
+            Msg::ConfirmSubmit => write!(f, "提出しますか？"),
+            Msg::NotSubmitted => write!(f, "提出しませんでした。"),
+            Msg::Retrying(e, retry, retries) => write!(
+                f,
+                "ジャッジサーバーに接続できませんでした: {e}。再試行します（{retry}/{retries}）"
+            ),
+            Msg::JudgeTimeout(secs) => write!(
+                f,
+                "{secs} 秒経ってもジャッジが終わらないため、待つのをやめました（judge_timeout）。"
+            ),
+            Msg::JudgeInterrupted => write!(
+                f,
+                "すべてのテストケースのジャッジが終わる前にジャッジサーバーが止まりました。"
+            ),
+            Msg::Cancelled => write!(f, "キャンセルしました。"),
+            Msg::CancelUnacknowledged => write!(
+                f,
+                "キャンセルしましたが、ジャッジサーバーから確認の応答がありませんでした。\
+                サーバーではジャッジが続いているかもしれません。"
+            ),
+            Msg::InvalidMessage(e) => write!(
+                f,
+                "ジャッジサーバーから壊れたメッセージが届きました: {e}\n\
+                何度も起きる場合は mmagolf を更新してください。"
+            ),
+            Msg::UnexpectedMessage(m) => write!(
+                f,
+                "ジャッジサーバーから予期しないメッセージが届きました: {m}\n\
+                何度も起きる場合は mmagolf を更新してください。"
+            ),
+            Msg::InvalidSubmission(line, s) => {
+                write!(f, "submissions ファイルの {line} 行目が壊れています: {s}")
+            }
+            Msg::MissingSubmittedFile(id) => {
+                write!(f, "提出 {id} のコードが submitted_files にありません。")
+            }
+            Msg::RankingNotUploaded(e) => {
+                write!(f, "ランキングをウェブサーバーにアップロードできませんでした: {e}")
+            }
+            Msg::SlackNotNotified(e) => {
+                write!(f, "Shortestの更新をSlackに投稿できませんでした: {e}")
+            }
+            Msg::UpgradeClient(server, client) => write!(
+                f,
+                "ジャッジサーバーのプロトコルはバージョン {server} ですが、\
+                この mmagolf はバージョン {client} までしか対応していません。\
+                mmagolf を更新してください。"
+            ),
+            Msg::UpgradeServer(server, client) => write!(
+                f,
+                "ジャッジサーバーのプロトコルはバージョン {server} で、\
+                この mmagolf のバージョン {client} より古いです。\
+                管理者にジャッジサーバーの更新を依頼してください。"
+            ),
+            Msg::BinarySourceUnsupported => {
+                write!(f, "ジャッジサーバーは UTF-8 でないコードを受け付けません。")
+            }
+            Msg::InvalidBase64(e) => write!(
+                f,
+                "base64 のデータを復号できませんでした: {e}\n\
+                mmagolf、mmagolf-back またはジャッジサーバーが古いかもしれません。"
+            ),
+            Msg::InvalidCommand(e) => write!(
+                f,
+                "mmagolf-back が mmagolf からのコマンドを読めませんでした: {e}\n\
+                mmagolf と mmagolf-back のバージョンが違うかもしれません。"
+            ),
+            Msg::InvalidBackOutput(e) => write!(
+                f,
+                "mmagolf-back の出力を読めませんでした: {e}\n\
+                mmagolf と mmagolf-back のバージョンが違うかもしれません。"
+            ),
+            Msg::UnknownUser(uid) => write!(f, "uid {uid} のユーザーが見つかりません。"),
+            Msg::OutputFailed(e) => write!(f, "出力を書き込めませんでした: {e}"),
+            Msg::CodeOrFile => write!(f, "--file か --code のどちらか一方を指定してください。"),
+            Msg::CountSummary(bytes, chars, lines) => {
+                write!(f, "サイズ: {bytes} B, 文字数: {chars}, 行数: {lines}")
+            }
+            Msg::CountNonAscii(chars, bytes) => write!(
+                f,
+                "非ASCII文字: {chars} 文字, {bytes} B（{:+} B）",
+                *bytes as isize - *chars as isize
+            ),
+            Msg::CountWhitespace(tabs, spaces) => write!(f, "タブ: {tabs}, スペース: {spaces}"),
+            Msg::CountTrailingWhitespace(bytes) => write!(f, "行末の空白: {bytes} B"),
+            Msg::CountInvisible => write!(f, "不可視文字:"),
+            Msg::UnknownServer(name, servers) => write!(
+                f,
+                "{name:?} という名前のジャッジサーバーはありません。\
+                /etc/mmagolf/config.toml にあるのは次のとおりです: {servers}"
+            ),
+        }
+    }
+}
@@ -0,0 +1,606 @@
+//! `mmagolf tui`: a full-screen dashboard of the problems, the ranking and the running judge.
+
+use crate::{current_user, problem_candidates, spawn_back, start_back, trim_eof_newline};
+use itertools::Itertools;
+use mmagolf::{
+    color::Palette, config::Config, format_table, messages::Msg, rank_submissions, Command,
+    JudgeStatus, OutputFormat, Problem, Progress, SortKey, Submission,
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    iter,
+    path::{Path, PathBuf},
+    process::{exit, Stdio},
+    sync::mpsc,
+    thread,
+};
+use termion::{
+    clear, cursor, event::Key, input::TermRead, raw::IntoRawMode, screen::AlternateScreen,
+};
+
+enum Event {
+    Key(Key),
+    /// a line of the `jsonl` output of `mmagolf-back`
+    Judge(Value),
+    /// `mmagolf-back` finished, with what it wrote to stderr
+    JudgeExited(String),
+}
+
+/// The submission or sample run shown in the bottom pane.
+struct Run {
+    title: String,
+    lang: String,
+    submit: bool,
+    test_cases: Vec<(String, JudgeStatus)>,
+    /// the result and messages shown under the test cases, without colors
+    lines: Vec<String>,
+    /// the verdict in `lines`, painted when drawn
+    verdict: Option<JudgeStatus>,
+    running: bool,
+}
+
+struct Dashboard<'a> {
+    config: &'a Config,
+    back_env: &'a [(&'a str, OsString)],
+    palette: Palette,
+    user: String,
+    problems: Vec<Problem>,
+    /// in the order they were made
+    submissions: Vec<Submission>,
+    /// source files in the current directory for each problem
+    files: HashMap<String, Vec<PathBuf>>,
+    selected: usize,
+    /// index into the files of the selected problem
+    file: usize,
+    run: Option<Run>,
+    status: String,
+}
+
+pub fn run(config: &Config, back_env: &[(&str, OsString)], palette: Palette) {
+    let mut dashboard = Dashboard {
+        config,
+        back_env,
+        palette,
+        user: current_user(),
+        problems: Vec::new(),
+        submissions: Vec::new(),
+        files: HashMap::new(),
+        selected: 0,
+        file: 0,
+        run: None,
+        status: String::new(),
+    };
+    if let Err(e) = dashboard.reload() {
+        eprint!("{e}");
+        exit(1)
+    }
+    let stdout = io::stdout().into_raw_mode().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(1)
+    });
+    let mut screen = AlternateScreen::from(stdout);
+    write!(screen, "{}", cursor::Hide).unwrap();
+    let (tx, rx) = mpsc::channel();
+    let keys = tx.clone();
+    thread::spawn(move || {
+        for key in io::stdin().keys().map_while(Result::ok) {
+            if keys.send(Event::Key(key)).is_err() {
+                break;
+            }
+        }
+    });
+    loop {
+        dashboard.draw(&mut screen).unwrap();
+        match rx.recv().unwrap() {
+            // Quitting would leave `mmagolf-back` judging with nobody reading its output.
+            Event::Key(Key::Char('q') | Key::Ctrl('c')) if dashboard.judge_running() => {
+                dashboard.status = Msg::TuiJudgeRunning.to_string();
+            }
+            Event::Key(Key::Char('q') | Key::Ctrl('c')) => break,
+            Event::Key(key) => dashboard.on_key(key, &tx),
+            Event::Judge(event) => dashboard.on_judge(event),
+            Event::JudgeExited(stderr) => dashboard.on_judge_exited(stderr),
+        }
+    }
+    write!(screen, "{}", cursor::Show).unwrap();
+}
+
+impl Dashboard<'_> {
+    /// Reads the problems from the judge server, and the submissions and files again.
+    fn reload(&mut self) -> Result<(), String> {
+        let problems = self.back(&Command::ListProblems {
+            problem_name: None,
+            json: true,
+        })?;
+        self.problems = serde_json::from_slice(&problems).map_err(|e| e.to_string())?;
+        self.selected = self.selected.min(self.problems.len().saturating_sub(1));
+        self.reload_submissions()?;
+        self.files = find_files(&self.problems, self.config);
+        self.file = 0;
+        Ok(())
+    }
+
+    fn reload_submissions(&mut self) -> Result<(), String> {
+        let submissions = self.back(&Command::History {
+            user: None,
+            all_users: true,
+            problem_name: None,
+            lang: None,
+            since: None,
+            until: None,
+            sort: SortKey::Time,
+            json: true,
+        })?;
+        self.submissions = serde_json::from_slice(&submissions).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Runs `mmagolf-back` and returns its stdout, or its stderr if it fails.
+    fn back(&self, command: &Command) -> Result<Vec<u8>, String> {
+        let output = spawn_back(
+            command,
+            Stdio::piped(),
+            Stdio::piped(),
+            self.config,
+            self.back_env,
+        );
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).to_string())
+        }
+    }
+
+    fn judge_running(&self) -> bool {
+        self.run.as_ref().is_some_and(|r| r.running)
+    }
+
+    fn selected_file(&self) -> Option<&PathBuf> {
+        let problem = self.problems.get(self.selected)?;
+        self.files.get(&problem.name)?.get(self.file)
+    }
+
+    fn on_key(&mut self, key: Key, tx: &mpsc::Sender<Event>) {
+        self.status.clear();
+        match key {
+            Key::Char('j') | Key::Down if self.selected + 1 < self.problems.len() => {
+                self.selected += 1;
+                self.file = 0;
+            }
+            Key::Char('k') | Key::Up if self.selected > 0 => {
+                self.selected -= 1;
+                self.file = 0;
+            }
+            Key::Char('f') => {
+                let n = self
+                    .problems
+                    .get(self.selected)
+                    .and_then(|p| self.files.get(&p.name))
+                    .map_or(0, Vec::len);
+                self.file = if n == 0 { 0 } else { (self.file + 1) % n };
+            }
+            Key::Char('r') => {
+                if let Err(e) = self.reload() {
+                    self.status = e.lines().join(" ");
+                }
+            }
+            Key::Char('s') => self.judge(true, tx),
+            Key::Char('t') => self.judge(false, tx),
+            _ => (),
+        }
+    }
+
+    /// Submits the selected file, or judges it on the sample cases if `submit` is not set.
+    fn judge(&mut self, submit: bool, tx: &mpsc::Sender<Event>) {
+        if self.judge_running() {
+            self.status = Msg::TuiJudgeRunning.to_string();
+            return;
+        }
+        let problem = match self.problems.get(self.selected) {
+            Some(p) => p.name.clone(),
+            None => return,
+        };
+        let file = match self.selected_file() {
+            Some(f) => f.clone(),
+            None => {
+                self.status = Msg::TuiNoFile(&problem).to_string();
+                return;
+            }
+        };
+        // Only files with a known extension are listed.
+        let lang = file_lang(&file, self.config).unwrap();
+        let code = match fs::read(&file) {
+            Ok(code) => trim_eof_newline(code, false),
+            Err(e) => {
+                self.status = format!("{}: {}", file.display(), e);
+                return;
+            }
+        };
+        let command = if submit {
+            Command::Submit {
+                lang: lang.clone(),
+                problem_name: problem.clone(),
+                code: base64::encode(code),
+                dry_run: false,
+                format: OutputFormat::Jsonl,
+                progress: Progress::None,
+            }
+        } else {
+            Command::Codetest {
+                lang: lang.clone(),
+                code: base64::encode(code),
+                input: None,
+                cases: Vec::new(),
+                problem_name: Some(problem.clone()),
+                format: OutputFormat::Jsonl,
+                progress: Progress::None,
+            }
+        };
+        let file = file.display().to_string();
+        self.run = Some(Run {
+            title: if submit {
+                Msg::TuiSubmitting(&problem, &file).to_string()
+            } else {
+                Msg::TuiRunningSamples(&problem, &file).to_string()
+            },
+            lang,
+            submit,
+            test_cases: Vec::new(),
+            lines: Vec::new(),
+            verdict: None,
+            running: true,
+        });
+        let mut child = start_back(
+            &command,
+            Stdio::piped(),
+            Stdio::piped(),
+            self.config,
+            self.back_env,
+        );
+        let stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if let Ok(event) = serde_json::from_str(&line) {
+                    let _ = tx.send(Event::Judge(event));
+                }
+            }
+            let mut s = String::new();
+            let _ = stderr.read_to_string(&mut s);
+            let _ = child.wait();
+            let _ = tx.send(Event::JudgeExited(s));
+        });
+    }
+
+    fn on_judge(&mut self, event: Value) {
+        let run = match &mut self.run {
+            Some(run) => run,
+            None => return,
+        };
+        let str = |key: &str| event[key].as_str().unwrap_or_default();
+        match str("type") {
+            "test_case_names" => {
+                run.test_cases = event["names"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|n| Some((n.as_str()?.to_string(), JudgeStatus::Wj)))
+                    .collect();
+            }
+            "test_case_result" => {
+                let status =
+                    JudgeStatus::from_name(str("verdict"), event["time"].as_u64().unwrap_or(0));
+                if let (Some(case), Some(status)) = (
+                    run.test_cases.iter_mut().find(|(n, _)| n == str("name")),
+                    status,
+                ) {
+                    case.1 = status;
+                }
+            }
+            "compile_error" => {
+                let code = event["exit_code"].as_i64().unwrap_or_default() as i32;
+                run.lines.extend(
+                    Msg::CompileError(code)
+                        .to_string()
+                        .lines()
+                        .map(String::from),
+                );
+                run.lines.extend(str("stderr").lines().map(String::from));
+            }
+            "not_such_problem" => run
+                .lines
+                .push(Msg::NotSuchProblem(str("problem_name")).to_string()),
+            "not_such_lang" => run.lines.push(Msg::NotSuchLang(str("lang")).to_string()),
+            "summary" => {
+                let status =
+                    JudgeStatus::from_name(str("verdict"), event["time"].as_u64().unwrap_or(0));
+                if let Some(status) = status {
+                    let verdict = status.paint(&without_colors(&self.palette));
+                    let size = event["size"].as_u64().unwrap_or_default() as usize;
+                    run.lines.push(Msg::Result(&verdict, size).to_string());
+                    run.verdict = Some(status);
+                }
+                if event["shortest"] == true {
+                    run.lines.push(Msg::Shortest.to_string());
+                } else if event["language_shortest"] == true {
+                    run.lines.push(Msg::LanguageShortest(&run.lang).to_string());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn on_judge_exited(&mut self, stderr: String) {
+        let submit = match &mut self.run {
+            Some(run) => {
+                run.running = false;
+                run.lines.extend(stderr.lines().map(String::from));
+                run.submit
+            }
+            None => return,
+        };
+        if submit {
+            if let Err(e) = self.reload_submissions() {
+                self.status = e.lines().join(" ");
+            }
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = termion::terminal_size()?;
+        let (width, height) = (width as usize, height as usize);
+        write!(out, "{}", clear::All)?;
+        let top = (height.saturating_sub(2) / 2).max(3);
+        let left = width / 2;
+        let (ranked, _) = rank_submissions(self.submissions.clone());
+        self.draw_problems(out, &ranked, 1, left.saturating_sub(1), top)?;
+        self.draw_ranking(out, &ranked, left + 1, width - left, top)?;
+        self.draw_run(out, top + 2, width, height.saturating_sub(top + 3))?;
+        let status = fit(&self.status, width);
+        write!(
+            out,
+            "{}{}{}{}",
+            cursor::Goto(1, height.saturating_sub(1).max(1) as u16),
+            status,
+            cursor::Goto(1, height.max(1) as u16),
+            fit(&Msg::TuiHelp.to_string(), width)
+        )?;
+        out.flush()
+    }
+
+    fn draw_problems(
+        &self,
+        out: &mut impl Write,
+        ranked: &HashMap<String, Vec<Submission>>,
+        x: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<()> {
+        let rows: Vec<_> = iter::once(
+            ["", "problem", "shortest", "lang", "user", "mine", "file"]
+                .map(String::from)
+                .to_vec(),
+        )
+        .chain(self.problems.iter().enumerate().map(|(i, p)| {
+            let shortest = ranked.get(&p.name).and_then(|r| r.first());
+            let mine = self
+                .submissions
+                .iter()
+                .filter(|s| s.problem == p.name && s.user == self.user)
+                .map(|s| s.size)
+                .min();
+            let file = match self.files.get(&p.name).map(Vec::as_slice) {
+                None | Some([]) => String::new(),
+                Some(files) => {
+                    let i = if i == self.selected { self.file } else { 0 };
+                    let file = files[i].display().to_string();
+                    if files.len() == 1 {
+                        file
+                    } else {
+                        format!("{file} ({}/{})", i + 1, files.len())
+                    }
+                }
+            };
+            vec![
+                if i == self.selected { ">" } else { "" }.to_string(),
+                p.name.clone(),
+                shortest
+                    .map(|s| format!("{} B", s.size))
+                    .unwrap_or_default(),
+                shortest.map(|s| s.lang.clone()).unwrap_or_default(),
+                shortest.map(|s| s.user.clone()).unwrap_or_default(),
+                mine.map(|m| format!("{m} B")).unwrap_or_default(),
+                file,
+            ]
+        }))
+        .collect();
+        let lines = format_table(&rows, &[2, 5]);
+        // Keep the selected problem in sight.
+        let skip = (self.selected + 2).saturating_sub(height);
+        for (y, (i, line)) in iter::once((None, &lines[0]))
+            .chain(
+                lines
+                    .iter()
+                    .enumerate()
+                    .skip(1 + skip)
+                    .map(|(i, l)| (Some(i - 1), l)),
+            )
+            .take(height)
+            .enumerate()
+        {
+            let line = fit(line, width);
+            let line = if i.is_none() || i == Some(self.selected) {
+                self.palette.bold(line)
+            } else {
+                line
+            };
+            write!(out, "{}{}", cursor::Goto(x as u16, (y + 1) as u16), line)?;
+        }
+        Ok(())
+    }
+
+    fn draw_ranking(
+        &self,
+        out: &mut impl Write,
+        ranked: &HashMap<String, Vec<Submission>>,
+        x: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<()> {
+        let problem = match self.problems.get(self.selected) {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let ranking = ranked.get(&problem.name).map_or(&[][..], Vec::as_slice);
+        let rank_rows: Vec<_> =
+            iter::once(["rank", "size", "lang", "user"].map(String::from).to_vec())
+                .chain(ranking.iter().enumerate().map(|(i, s)| {
+                    vec![
+                        (i + 1).to_string(),
+                        format!("{} B", s.size),
+                        s.lang.clone(),
+                        s.user.clone(),
+                    ]
+                }))
+                .collect();
+        let lang_rows: Vec<_> = iter::once(["lang", "size", "user"].map(String::from).to_vec())
+            .chain(
+                ranking
+                    .iter()
+                    .unique_by(|s| &s.lang)
+                    .map(|s| vec![s.lang.clone(), format!("{} B", s.size), s.user.clone()]),
+            )
+            .collect();
+        let lang_lines = format_table(&lang_rows, &[1]);
+        // Share the height between the two tables, giving the ranking what is left.
+        let lang_height = lang_lines.len().min(height / 2);
+        let rank_height = height.saturating_sub(lang_height + 1);
+        let rank_lines = format_table(&rank_rows, &[0, 1]);
+        let blank = String::new();
+        let lines = rank_lines
+            .iter()
+            .take(rank_height)
+            .map(|l| (l, l == &rank_lines[0]))
+            .chain(iter::once((&blank, false)))
+            .chain(
+                lang_lines
+                    .iter()
+                    .take(lang_height)
+                    .map(|l| (l, l == &lang_lines[0])),
+            );
+        for (y, (line, header)) in lines.enumerate() {
+            let line = fit(line, width);
+            let line = if header {
+                self.palette.bold(line)
+            } else {
+                line
+            };
+            write!(out, "{}{}", cursor::Goto(x as u16, (y + 1) as u16), line)?;
+        }
+        Ok(())
+    }
+
+    fn draw_run(
+        &self,
+        out: &mut impl Write,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<()> {
+        let run = match &self.run {
+            Some(run) => run,
+            None => return Ok(()),
+        };
+        let name_width = run
+            .test_cases
+            .iter()
+            .map(|(n, _)| n.chars().count())
+            .max()
+            .unwrap_or(0);
+        // `name: AC  1234567 ms` and a gap
+        let cell_width = name_width + 18;
+        let columns = (width / cell_width).max(1);
+        let title = if run.running {
+            format!("{} ...", run.title)
+        } else {
+            run.title.clone()
+        };
+        let mut lines = vec![self.palette.bold(fit(&title, width))];
+        for row in &run.test_cases.iter().chunks(columns) {
+            lines.push(
+                row.map(|(name, status)| {
+                    let pad = " ".repeat(name_width - name.chars().count());
+                    format!("{pad}{name}: {}", status.paint(&self.palette))
+                })
+                .join("    "),
+            );
+        }
+        let plain = without_colors(&self.palette);
+        lines.extend(run.lines.iter().map(|l| {
+            // Paint after cutting so that no escape sequence is cut in half.
+            let line = fit(l, width);
+            match run.verdict {
+                Some(v) => line.replacen(&v.paint(&plain), &v.paint(&self.palette), 1),
+                None => line,
+            }
+        }));
+        for (i, line) in lines.iter().take(height).enumerate() {
+            write!(out, "{}{}", cursor::Goto(1, (y + i) as u16), line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Files with a known extension in the current directory and its subdirectories whose name
+/// or directory is the name of a problem, e.g. `fizzbuzz.hs` or `fizzbuzz/main.py`.
+fn find_files(problems: &[Problem], config: &Config) -> HashMap<String, Vec<PathBuf>> {
+    let read_dir = |dir: &Path| {
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .collect::<Vec<_>>()
+    };
+    let mut files: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for path in read_dir(Path::new("."))
+        .into_iter()
+        .flat_map(|p| if p.is_dir() { read_dir(&p) } else { vec![p] })
+        .filter(|p| p.is_file() && file_lang(p, config).is_some())
+    {
+        let path = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+        for candidate in problem_candidates(&path) {
+            if problems.iter().any(|p| p.name == candidate) {
+                files.entry(candidate).or_default().push(path.clone());
+            }
+        }
+    }
+    for files in files.values_mut() {
+        files.sort();
+        files.dedup();
+    }
+    files
+}
+
+/// The language for the extension of `file`.
+fn file_lang(file: &Path, config: &Config) -> Option<String> {
+    let ext = file.extension()?.to_string_lossy();
+    config.extensions.get(&*ext).cloned()
+}
+
+fn without_colors(palette: &Palette) -> Palette {
+    Palette {
+        enabled: false,
+        ..palette.clone()
+    }
+}
+
+/// Cuts `s` to `width` characters.
+fn fit(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}